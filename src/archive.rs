@@ -0,0 +1,491 @@
+use anyhow::{bail, Context, Result};
+use flate2::write::GzDecoder;
+use std::io::Write;
+use zstd::stream::{raw, zio};
+
+// Tar archives are made of 512 byte blocks
+const BLOCK: usize = 512;
+
+/// How a test data archive is compressed.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    // A plain tar, as docker's archive API sends
+    None,
+}
+
+impl Compression {
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        match content_type.split(';').next()?.trim() {
+            "application/gzip" | "application/x-gzip" | "application/x-tar+gzip" => {
+                Some(Compression::Gzip)
+            }
+            "application/zstd" | "application/x-tar+zstd" => Some(Compression::Zstd),
+            "application/x-tar" => Some(Compression::None),
+            _ => None,
+        }
+    }
+}
+
+enum Decoder {
+    Gzip(GzDecoder<Vec<u8>>),
+    // The plain writer, as only it reports a frame that was cut off
+    Zstd(zio::Writer<Vec<u8>, raw::Decoder<'static>>),
+    None,
+}
+
+/// Unpacks a tar archive as its bytes arrive, so large archives never
+/// have to be held in memory or written to disk first.
+pub struct Archive {
+    decoder: Decoder,
+    files: TarFiles,
+}
+
+impl Archive {
+    pub fn new(compression: Compression) -> Result<Self> {
+        let decoder = match compression {
+            Compression::Gzip => Decoder::Gzip(GzDecoder::new(Vec::new())),
+            Compression::Zstd => Decoder::Zstd(zio::Writer::new(Vec::new(), raw::Decoder::new()?)),
+            Compression::None => Decoder::None,
+        };
+        Ok(Archive {
+            decoder,
+            files: TarFiles::new(),
+        })
+    }
+
+    /// Feeds the next chunk of the archive, returning the pieces of regular files
+    /// found in it as (path, bytes).
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+        let unpacked = match &mut self.decoder {
+            Decoder::Gzip(decoder) => {
+                decoder
+                    .write_all(chunk)
+                    .context("Unable to decompress gzip")?;
+                std::mem::take(decoder.get_mut())
+            }
+            Decoder::Zstd(decoder) => {
+                decoder
+                    .write_all(chunk)
+                    .context("Unable to decompress zstd")?;
+                std::mem::take(decoder.writer_mut())
+            }
+            Decoder::None => return self.files.feed(chunk),
+        };
+        self.files.feed(&unpacked)
+    }
+
+    /// Flushes the decoder once the whole archive has been fed, failing if it was
+    /// cut off.
+    pub fn finish(mut self) -> Result<Vec<(String, Vec<u8>)>> {
+        let unpacked = match &mut self.decoder {
+            Decoder::Gzip(decoder) => {
+                decoder.try_finish().context("Unable to decompress gzip")?;
+                std::mem::take(decoder.get_mut())
+            }
+            Decoder::Zstd(decoder) => {
+                decoder.finish().context("Unable to decompress zstd")?;
+                std::mem::take(decoder.writer_mut())
+            }
+            Decoder::None => Vec::new(),
+        };
+        let pieces = self.files.feed(&unpacked)?;
+        if !self.files.finished() {
+            bail!("Archive was cut off");
+        }
+        Ok(pieces)
+    }
+}
+
+// What the data following a header is
+enum Contents {
+    File(String),
+    // GNU long name or pax header, applies to the next entry
+    Meta { long_name: bool, bytes: Vec<u8> },
+    Skip,
+}
+
+// Reads the regular files out of an uncompressed tar stream
+struct TarFiles {
+    header: Vec<u8>,
+    contents: Contents,
+    // Bytes of data and then padding left in the current entry
+    remaining: u64,
+    padding: u64,
+    // Name for the next entry from a GNU long name or pax header
+    next_name: Option<String>,
+    // Set at the first all zero block, which ends the archive
+    done: bool,
+}
+
+impl TarFiles {
+    fn new() -> Self {
+        TarFiles {
+            header: Vec::with_capacity(BLOCK),
+            contents: Contents::Skip,
+            remaining: 0,
+            padding: 0,
+            next_name: None,
+            done: false,
+        }
+    }
+
+    fn feed(&mut self, mut bytes: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+        let mut pieces: Vec<(String, Vec<u8>)> = Vec::new();
+        while !bytes.is_empty() && !self.done {
+            if self.remaining > 0 {
+                let take = bytes.len().min(self.remaining as usize);
+                match &mut self.contents {
+                    Contents::File(name) => match pieces.last_mut() {
+                        Some((last, data)) if last == name => {
+                            data.extend_from_slice(&bytes[..take])
+                        }
+                        _ => pieces.push((name.clone(), bytes[..take].to_vec())),
+                    },
+                    Contents::Meta { bytes: meta, .. } => meta.extend_from_slice(&bytes[..take]),
+                    Contents::Skip => {}
+                }
+                self.remaining -= take as u64;
+                bytes = &bytes[take..];
+                if self.remaining == 0 {
+                    self.end_contents();
+                }
+            } else if self.padding > 0 {
+                let take = bytes.len().min(self.padding as usize);
+                self.padding -= take as u64;
+                bytes = &bytes[take..];
+            } else {
+                let take = bytes.len().min(BLOCK - self.header.len());
+                self.header.extend_from_slice(&bytes[..take]);
+                bytes = &bytes[take..];
+                if self.header.len() == BLOCK {
+                    let header = std::mem::replace(&mut self.header, Vec::with_capacity(BLOCK));
+                    self.start_entry(&header)?;
+                }
+            }
+        }
+        Ok(pieces)
+    }
+
+    // Whether the archive ended on an entry boundary
+    fn finished(&self) -> bool {
+        self.done || (self.remaining == 0 && self.padding == 0 && self.header.is_empty())
+    }
+
+    fn start_entry(&mut self, header: &[u8]) -> Result<()> {
+        if header.iter().all(|&byte| byte == 0) {
+            self.done = true;
+            return Ok(());
+        }
+        let checksum = octal(&header[148..156]).context("Invalid tar header checksum")?;
+        let sum: u64 = header
+            .iter()
+            .enumerate()
+            .map(|(index, &byte)| match index {
+                148..156 => u64::from(b' '),
+                _ => u64::from(byte),
+            })
+            .sum();
+        if sum != checksum {
+            bail!("Corrupt tar header");
+        }
+
+        let size = size(&header[124..136]).context("Invalid tar entry size")?;
+        let name = match self.next_name.take() {
+            Some(name) => name,
+            None => {
+                let name = text(&header[0..100]);
+                // ustar splits long paths into a prefix and a name
+                let prefix = match &header[257..262] {
+                    b"ustar" => text(&header[345..500]),
+                    _ => String::new(),
+                };
+                if prefix.is_empty() {
+                    name
+                } else {
+                    format!("{}/{}", prefix, name)
+                }
+            }
+        };
+        self.contents = match header[156] {
+            b'0' | 0 => Contents::File(name.trim_start_matches("./").to_string()),
+            b'L' => Contents::Meta {
+                long_name: true,
+                bytes: Vec::new(),
+            },
+            b'x' => Contents::Meta {
+                long_name: false,
+                bytes: Vec::new(),
+            },
+            // Directories, links and global pax headers
+            _ => Contents::Skip,
+        };
+        self.remaining = size;
+        self.padding = (BLOCK as u64 - size % BLOCK as u64) % BLOCK as u64;
+        if size == 0 {
+            self.end_contents();
+        }
+        Ok(())
+    }
+
+    fn end_contents(&mut self) {
+        if let Contents::Meta { long_name, bytes } = &self.contents {
+            self.next_name = if *long_name {
+                Some(text(bytes))
+            } else {
+                pax_path(bytes)
+            };
+        }
+        self.contents = Contents::Skip;
+    }
+}
+
+// A NUL terminated string field
+fn text(field: &[u8]) -> String {
+    let end = field
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+fn octal(field: &[u8]) -> Result<u64> {
+    let digits = text(field);
+    let digits = digits.trim_matches(|c: char| c == ' ' || c == '\0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    Ok(u64::from_str_radix(digits, 8)?)
+}
+
+// Sizes over 8GB are stored as big endian binary with the high bit set
+fn size(field: &[u8]) -> Result<u64> {
+    if field[0] & 0x80 != 0 {
+        let mut size: u64 = u64::from(field[0] & 0x7f);
+        for &byte in &field[1..] {
+            size = size
+                .checked_mul(256)
+                .context("Tar entry is too large")?
+                .checked_add(u64::from(byte))
+                .context("Tar entry is too large")?;
+        }
+        return Ok(size);
+    }
+    octal(field)
+}
+
+// The path record of a pax header, whose records look like "<len> path=<path>\n"
+fn pax_path(records: &[u8]) -> Option<String> {
+    String::from_utf8_lossy(records).lines().find_map(|record| {
+        let (_, field) = record.split_once(' ')?;
+        field.strip_prefix("path=").map(str::to_string)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use std::collections::HashMap;
+    use tar::{Builder, EntryType, Header};
+
+    // Longer than the 100 bytes a tar header has room for
+    fn long_path() -> String {
+        format!("{}/input.txt", "nested".repeat(20))
+    }
+
+    fn file(header: &mut Header, size: usize) {
+        header.set_size(size as u64);
+        header.set_mode(0o644);
+        header.set_entry_type(EntryType::Regular);
+        header.set_cksum();
+    }
+
+    // input.txt spans several blocks and is followed by the kinds of entries a
+    // reader has to step over
+    fn data() -> Vec<u8> {
+        (0..3000u32)
+            .flat_map(|n| format!("{}\n", n).into_bytes())
+            .collect()
+    }
+
+    fn gnu() -> Vec<u8> {
+        let mut builder = Builder::new(Vec::new());
+        let mut dir = Header::new_gnu();
+        dir.set_entry_type(EntryType::Directory);
+        dir.set_size(0);
+        dir.set_mode(0o755);
+        builder.append_data(&mut dir, "./", &[][..]).unwrap();
+        let mut header = Header::new_gnu();
+        file(&mut header, data().len());
+        builder
+            .append_data(&mut header, "./input.txt", &data()[..])
+            .unwrap();
+        let mut header = Header::new_gnu();
+        file(&mut header, 0);
+        builder
+            .append_data(&mut header, "empty.txt", &[][..])
+            .unwrap();
+        // Written with a GNU long name entry
+        let mut header = Header::new_gnu();
+        file(&mut header, 5);
+        builder
+            .append_data(&mut header, long_path(), &b"long\n"[..])
+            .unwrap();
+        let mut link = Header::new_gnu();
+        link.set_entry_type(EntryType::Symlink);
+        link.set_size(0);
+        builder
+            .append_link(&mut link, "link.txt", "input.txt")
+            .unwrap();
+        let mut header = Header::new_gnu();
+        file(&mut header, 4);
+        builder
+            .append_data(&mut header, "output.txt", &b"out\n"[..])
+            .unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    fn pax() -> Vec<u8> {
+        let mut builder = Builder::new(Vec::new());
+        let mut header = Header::new_ustar();
+        file(&mut header, data().len());
+        builder
+            .append_data(&mut header, "input.txt", &data()[..])
+            .unwrap();
+        // The pax path record overrides the name in the header that follows
+        builder
+            .append_pax_extensions([("path", long_path().as_bytes())])
+            .unwrap();
+        let mut header = Header::new_ustar();
+        header.set_path("truncated").unwrap();
+        file(&mut header, 5);
+        builder.append(&header, &b"long\n"[..]).unwrap();
+        // ustar keeps paths up to 255 bytes by splitting off a prefix
+        let mut header = Header::new_ustar();
+        header
+            .set_path(format!("{}/output.txt", "prefix".repeat(15)))
+            .unwrap();
+        file(&mut header, 4);
+        builder.append(&header, &b"out\n"[..]).unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    fn unpack(compression: Compression, chunks: &[&[u8]]) -> Result<HashMap<String, Vec<u8>>> {
+        let mut archive = Archive::new(compression)?;
+        let mut files: HashMap<String, Vec<u8>> = HashMap::new();
+        let mut add = |pieces: Vec<(String, Vec<u8>)>| {
+            for (path, bytes) in pieces {
+                files.entry(path).or_default().extend(bytes);
+            }
+        };
+        for chunk in chunks {
+            add(archive.feed(chunk)?);
+        }
+        add(archive.finish()?);
+        Ok(files)
+    }
+
+    fn gnu_files() -> HashMap<String, Vec<u8>> {
+        HashMap::from([
+            (String::from("input.txt"), data()),
+            (long_path(), b"long\n".to_vec()),
+            (String::from("output.txt"), b"out\n".to_vec()),
+        ])
+    }
+
+    fn pax_files() -> HashMap<String, Vec<u8>> {
+        HashMap::from([
+            (String::from("input.txt"), data()),
+            (long_path(), b"long\n".to_vec()),
+            (
+                format!("{}/output.txt", "prefix".repeat(15)),
+                b"out\n".to_vec(),
+            ),
+        ])
+    }
+
+    // Splitting the archive at any point, or feeding it in small uneven chunks,
+    // unpacks the same files
+    fn assert_unpacks(
+        compression: Compression,
+        archive: &[u8],
+        expected: &HashMap<String, Vec<u8>>,
+    ) {
+        for split in 0..=archive.len() {
+            let (head, tail) = archive.split_at(split);
+            assert_eq!(
+                &unpack(compression, &[head, tail]).unwrap(),
+                expected,
+                "split at {}",
+                split
+            );
+        }
+        for size in [1, 7, 511, 513] {
+            let chunks: Vec<&[u8]> = archive.chunks(size).collect();
+            assert_eq!(
+                &unpack(compression, &chunks).unwrap(),
+                expected,
+                "chunks of {}",
+                size
+            );
+        }
+    }
+
+    #[test]
+    fn gnu_archive() {
+        assert_unpacks(Compression::None, &gnu(), &gnu_files());
+    }
+
+    #[test]
+    fn pax_archive() {
+        assert_unpacks(Compression::None, &pax(), &pax_files());
+    }
+
+    #[test]
+    fn gzip_archive() {
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&gnu()).unwrap();
+        assert_unpacks(Compression::Gzip, &encoder.finish().unwrap(), &gnu_files());
+    }
+
+    #[test]
+    fn zstd_archive() {
+        let compressed = zstd::encode_all(&pax()[..], 0).unwrap();
+        assert_unpacks(Compression::Zstd, &compressed, &pax_files());
+    }
+
+    #[test]
+    fn cut_off() {
+        let archive = gnu();
+        // Ends in the middle of input.txt
+        assert!(unpack(Compression::None, &[&archive[..2000]]).is_err());
+        let compressed = zstd::encode_all(&archive[..], 0).unwrap();
+        assert!(unpack(Compression::Zstd, &[&compressed[..compressed.len() / 2]]).is_err());
+    }
+
+    #[test]
+    fn corrupt_header() {
+        let mut archive = gnu();
+        archive[600] ^= 1;
+        assert!(unpack(Compression::None, &[&archive]).is_err());
+    }
+
+    #[test]
+    fn content_types() {
+        assert_eq!(
+            Compression::from_content_type("application/gzip"),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(
+            Compression::from_content_type("application/zstd; charset=binary"),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(
+            Compression::from_content_type("application/x-tar"),
+            Some(Compression::None)
+        );
+        assert_eq!(Compression::from_content_type("application/json"), None);
+    }
+}
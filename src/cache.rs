@@ -1,126 +1,792 @@
-use crate::error::Errors;
-use anyhow::anyhow;
-use anyhow::{Context, Result};
-use md5;
+use crate::source::SubmissionSource;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::Path;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
 use tokio::fs;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::RwLock;
+use tracing::{error, info};
 
+// Test data is stored once per content under its SHA-256, problems link to it
+const OBJECTS: &str = "problems/objects";
+// Every download of a problem gets its own dir, `problems/<id>` is a symlink to the current one
+const VERSIONS: &str = "problems/versions";
+
+// One lock per problem so only one job downloads it at a time
+static DOWNLOAD_LOCKS: LazyLock<Mutex<HashMap<i32, Arc<tokio::sync::Mutex<()>>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// Numbers the temporary files downloads are written to
+static DOWNLOADS: AtomicU64 = AtomicU64::new(0);
+
+// Downloads share the store, eviction needs it to itself so it never removes
+// an object a download is about to link in
+static STORE: LazyLock<RwLock<()>> = LazyLock::new(|| RwLock::new(()));
+
+// How many running jobs use each problem, eviction leaves these alone
+static PINS: LazyLock<Mutex<HashMap<i32, usize>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Keeps a problem from being evicted while it is held.
+pub struct Pin {
+    problem_id: i32,
+}
+
+pub fn pin(problem_id: i32) -> Pin {
+    *PINS.lock().unwrap().entry(problem_id).or_default() += 1;
+    Pin { problem_id }
+}
+
+impl Drop for Pin {
+    fn drop(&mut self) {
+        let mut pins = PINS.lock().unwrap();
+        if let Some(count) = pins.get_mut(&self.problem_id) {
+            *count -= 1;
+            if *count == 0 {
+                pins.remove(&self.problem_id);
+            }
+        }
+    }
+}
+
+/// A cached problem.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub problem_id: i32,
+    pub manifest: Manifest,
+    pub last_used: Option<DateTime<Utc>>,
+}
+
+impl Entry {
+    pub fn size(&self) -> u64 {
+        self.manifest.input.size + self.manifest.output.size
+    }
+}
+
+// A stored file, hashed once when it was downloaded
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct Blob {
+    pub sha256: String,
+    // Kept for comparing with the sums ravel sends
+    pub md5: String,
+    // Uncompressed size
+    pub size: u64,
+    // Stored zstd compressed as `<sha256>.zst`
+    #[serde(default)]
+    pub compressed: bool,
+}
+
+impl Blob {
+    fn object(&self, root: &Path) -> PathBuf {
+        root.join(OBJECTS).join(self.object_name())
+    }
+
+    fn object_name(&self) -> String {
+        if self.compressed {
+            format!("{}.zst", self.sha256)
+        } else {
+            self.sha256.clone()
+        }
+    }
+
+    // Name of the file in a problem dir, e.g. input.txt or input.txt.zst
+    fn file_name(&self, name: &str) -> String {
+        if self.compressed {
+            format!("{}.txt.zst", name)
+        } else {
+            format!("{}.txt", name)
+        }
+    }
+}
+
+/// What `problems/<id>/manifest.json` records about a cached problem.
 #[derive(Deserialize, Serialize, Debug, Clone)]
-pub struct Problem {
-    problem_input: String,
-    problem_output: String,
+pub struct Manifest {
+    pub input: Blob,
+    pub output: Blob,
 }
 
-pub async fn check_cache(problem_id: &i32, input_sum: String, output_sum: String) -> Result<bool> {
-    if !Path::exists(Path::new(&format!("problems/{}", problem_id))) {
-        return Ok(false);
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Part {
+    Input,
+    Output,
+}
+
+// A file being downloaded, hashed as it is written
+struct BlobWriter {
+    path: PathBuf,
+    file: BufWriter<fs::File>,
+    sha256: Sha256,
+    md5: md5::Context,
+    size: u64,
+}
+
+impl BlobWriter {
+    async fn create(root: &Path) -> Result<Self> {
+        let path = root.join(OBJECTS).join(format!(
+            "tmp-{}-{}",
+            std::process::id(),
+            DOWNLOADS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = fs::File::create(&path)
+            .await
+            .with_context(|| format!("Unable to create {}", path.display()))?;
+        Ok(BlobWriter {
+            path,
+            file: BufWriter::new(file),
+            sha256: Sha256::new(),
+            md5: md5::Context::new(),
+            size: 0,
+        })
     }
 
-    let input = fs::read_to_string(format!("problems/{}/input.txt", problem_id))
-        .await
-        .with_context(|| format!("Failed to read problem {}'s input.", problem_id))?;
-    let output = fs::read_to_string(format!("problems/{}/output.txt", problem_id))
+    async fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.sha256.update(bytes);
+        self.md5.consume(bytes);
+        self.size += bytes.len() as u64;
+        self.file.write_all(bytes).await?;
+        Ok(())
+    }
+
+    // Moves the file into the store under its hash, read-only, as long as it has
+    // the checksum the source promised. An empty checksum means the source doesn't
+    // know it yet, like DOMjudge for a test case it hasn't sent before.
+    async fn finish(mut self, root: &Path, expected_md5: &str, compressed: bool) -> Result<Blob> {
+        self.file.flush().await?;
+        self.file.get_ref().sync_all().await?;
+        let blob = Blob {
+            sha256: format!("{:x}", self.sha256.finalize()),
+            md5: format!("{:x}", self.md5.compute()),
+            size: self.size,
+            compressed,
+        };
+        if !expected_md5.is_empty() && blob.md5 != expected_md5 {
+            fs::remove_file(&self.path).await?;
+            bail!(
+                "Downloaded data has checksum {} but {} was expected",
+                blob.md5,
+                expected_md5
+            );
+        }
+
+        let object = blob.object(root);
+        if fs::try_exists(&object).await? {
+            fs::remove_file(&self.path).await?;
+        } else {
+            if blob.compressed {
+                self.path = compress(&self.path).await?;
+            }
+            let mut perms = fs::metadata(&self.path).await?.permissions();
+            perms.set_readonly(true);
+            fs::set_permissions(&self.path, perms).await?;
+            fs::rename(&self.path, &object)
+                .await
+                .with_context(|| format!("Unable to store {}", object.display()))?;
+        }
+        Ok(blob)
+    }
+}
+
+/// Where a source writes a problem's data while downloading it.
+pub struct ProblemSink {
+    input: BlobWriter,
+    output: BlobWriter,
+}
+
+impl ProblemSink {
+    pub async fn write(&mut self, part: Part, bytes: &[u8]) -> Result<()> {
+        match part {
+            Part::Input => self.input.write(bytes).await,
+            Part::Output => self.output.write(bytes).await,
+        }
+    }
+}
+
+// Compresses a downloaded file next to it, returning the compressed file
+async fn compress(path: &Path) -> Result<PathBuf> {
+    let compressed = path.with_extension("zst");
+    let (from, to) = (path.to_path_buf(), compressed.clone());
+    tokio::task::spawn_blocking(move || -> Result<()> {
+        let input = std::fs::File::open(&from)?;
+        let output = std::fs::File::create(&to)?;
+        zstd::stream::copy_encode(input, &output, 0)?;
+        output.sync_all()?;
+        Ok(())
+    })
+    .await?
+    .with_context(|| format!("Unable to compress {}", path.display()))?;
+    fs::remove_file(path).await?;
+    Ok(compressed)
+}
+
+pub async fn read_manifest(root: &Path, problem_id: i32) -> Result<Manifest> {
+    let path = problem_dir(root, problem_id).join("manifest.json");
+    let manifest = fs::read_to_string(&path)
         .await
-        .with_context(|| format!("Failed to read problem {}'s output.", problem_id))?;
+        .with_context(|| format!("Unable to read {}", path.display()))?;
+    serde_json::from_str(&manifest).with_context(|| format!("Unable to parse {}", path.display()))
+}
 
-    let _input2 = input.clone();
+// Where problem `problem_id` is cached under the work dir `root`
+fn problem_dir(root: &Path, problem_id: i32) -> PathBuf {
+    root.join("problems").join(problem_id.to_string())
+}
 
-    if format!("{:x}", md5::compute(input)) != input_sum
-        || format!("{:x}", md5::compute(output)) != output_sum
+/// Whether problem `problem_id` is cached with the given checksums. Only the
+/// manifest is read, the data was hashed when it was downloaded.
+pub async fn check_cache(
+    root: &Path,
+    problem_id: &i32,
+    input_sum: String,
+    output_sum: String,
+) -> Result<bool> {
+    // Problems cached before the manifest existed are downloaded again
+    if !problem_dir(root, *problem_id)
+        .join("manifest.json")
+        .exists()
     {
         return Ok(false);
     }
+    let manifest = read_manifest(root, *problem_id).await?;
+    if manifest.input.md5 != input_sum || manifest.output.md5 != output_sum {
+        return Ok(false);
+    }
 
-    return Ok(true);
+    // A truncated or missing file means the cache was damaged, compressed files
+    // are only checked for when they are decompressed
+    for (name, blob) in [("input", &manifest.input), ("output", &manifest.output)] {
+        let path = problem_dir(root, *problem_id).join(blob.file_name(name));
+        match fs::metadata(&path).await {
+            Ok(metadata) if blob.compressed || metadata.len() == blob.size => {}
+            _ => return Ok(false),
+        }
+    }
+    Ok(true)
 }
 
-pub async fn cache_problem(
-    creds: &HashMap<&str, String>,
-    client: &reqwest::Client,
-    url: &String,
+/// Makes sure problem `problem_id` is cached with the given checksums. When
+/// several jobs need the same missing problem one downloads it and the others
+/// wait for it.
+pub async fn ensure_cached(
+    root: &Path,
+    source: &dyn SubmissionSource,
+    problem_id: i32,
+    input_sum: &str,
+    output_sum: &str,
+    budget: Option<u64>,
+    compress: bool,
+) -> Result<()> {
+    if fresh(root, problem_id, input_sum, output_sum).await {
+        touch(root, problem_id).await;
+        return Ok(());
+    }
+    let result = fetch(
+        root, source, problem_id, input_sum, output_sum, budget, compress,
+    )
+    .await;
+    if result.is_ok() {
+        touch(root, problem_id).await;
+    }
+    result
+}
+
+/// Caches a problem before any submission needs it. This doesn't count as a use
+/// for eviction, and with a `budget` in bytes nothing is downloaded once the
+/// store has filled it. Returns whether the problem is cached.
+pub async fn prefetch(
+    root: &Path,
+    source: &dyn SubmissionSource,
+    problem_id: i32,
+    input_sum: &str,
+    output_sum: &str,
+    budget: Option<u64>,
+    compress: bool,
+) -> Result<bool> {
+    if fresh(root, problem_id, input_sum, output_sum).await {
+        return Ok(true);
+    }
+    if let Some(budget) = budget {
+        if stored(root).await? >= budget {
+            return Ok(false);
+        }
+    }
+    // Making room would evict problems that were actually used
+    fetch(
+        root, source, problem_id, input_sum, output_sum, None, compress,
+    )
+    .await?;
+    Ok(true)
+}
+
+// Downloads a problem unless another job is already doing it
+async fn fetch(
+    root: &Path,
+    source: &dyn SubmissionSource,
     problem_id: i32,
+    input_sum: &str,
+    output_sum: &str,
+    budget: Option<u64>,
+    compress: bool,
 ) -> Result<()> {
-    if Path::exists(Path::new(&format!("problems/{}", problem_id))) {
-        fs::remove_dir_all(&format!("problems/{}", problem_id))
+    let lock = DOWNLOAD_LOCKS
+        .lock()
+        .unwrap()
+        .entry(problem_id)
+        .or_default()
+        .clone();
+    let result = {
+        let _download = lock.lock().await;
+        // Whoever held the lock before may have downloaded it already
+        if fresh(root, problem_id, input_sum, output_sum).await {
+            Ok(())
+        } else {
+            info!("Problem {} is missing from cache", problem_id);
+            cache_problem(
+                root, source, problem_id, input_sum, output_sum, budget, compress,
+            )
             .await
-            .with_context(|| format!("Unable to clear dir for problem {}.", problem_id))?;
+        }
+    };
+
+    // Forget the lock once nobody else is waiting on it
+    let mut locks = DOWNLOAD_LOCKS.lock().unwrap();
+    if Arc::strong_count(&lock) == 2 {
+        locks.remove(&problem_id);
+    }
+    result
+}
+
+async fn fresh(root: &Path, problem_id: i32, input_sum: &str, output_sum: &str) -> bool {
+    match check_cache(
+        root,
+        &problem_id,
+        input_sum.to_string(),
+        output_sum.to_string(),
+    )
+    .await
+    {
+        Ok(fresh) => fresh,
+        Err(err) => {
+            error!(
+                "Unable to read problem {} from cache: {:#}",
+                problem_id, err
+            );
+            false
+        }
+    }
+}
+
+/// Downloads a problem into the store. With a `budget` in bytes, other problems
+/// are evicted afterwards until the store fits in it again.
+pub async fn cache_problem(
+    root: &Path,
+    source: &dyn SubmissionSource,
+    problem_id: i32,
+    input_sum: &str,
+    output_sum: &str,
+    budget: Option<u64>,
+    compress: bool,
+) -> Result<()> {
+    {
+        let _store = STORE.read().await;
+        download(root, source, problem_id, input_sum, output_sum, compress).await?;
+    }
+
+    if let Some(budget) = budget {
+        // The download hasn't been used yet, so it would be the first to go
+        let _pin = pin(problem_id);
+        match evict(root, budget).await {
+            Ok(evicted) if !evicted.is_empty() => info!(
+                "Evicted problems {:?} to make room for problem {}",
+                evicted, problem_id
+            ),
+            Ok(_) => {}
+            Err(err) => error!("Unable to evict problems from the cache: {:#}", err),
+        }
+    }
+    Ok(())
+}
+
+// Streams problem `problem_id` from the source into the store and links it in
+// as `problems/<id>/input.txt` and `output.txt`. The files are put together in
+// a new dir that replaces the old one in a single step, so jobs copying from
+// the problem never see it half written. Data that doesn't match the checksums
+// is thrown away.
+async fn download(
+    root: &Path,
+    source: &dyn SubmissionSource,
+    problem_id: i32,
+    input_sum: &str,
+    output_sum: &str,
+    compress: bool,
+) -> Result<()> {
+    fs::create_dir_all(root.join(OBJECTS))
+        .await
+        .context("Unable to create the problem store")?;
+    fs::create_dir_all(root.join(VERSIONS))
+        .await
+        .context("Unable to create the problem store")?;
+    let mut sink = ProblemSink {
+        input: BlobWriter::create(root).await?,
+        output: BlobWriter::create(root).await?,
+    };
+    if let Err(err) = source.download(problem_id, &mut sink).await {
+        let _ = fs::remove_file(&sink.input.path).await;
+        let _ = fs::remove_file(&sink.output.path).await;
+        return Err(err);
     }
-    fs::create_dir(&format!("problems/{}", problem_id))
+    let input = sink.input.finish(root, input_sum, compress).await;
+    let output = sink.output.finish(root, output_sum, compress).await;
+    let manifest = Manifest {
+        input: input.with_context(|| format!("Input of problem {} is corrupt", problem_id))?,
+        output: output.with_context(|| format!("Output of problem {} is corrupt", problem_id))?,
+    };
+
+    let version = format!(
+        "{}-{}-{}",
+        problem_id,
+        std::process::id(),
+        DOWNLOADS.fetch_add(1, Ordering::Relaxed)
+    );
+    let dir = root.join(VERSIONS).join(&version);
+    fs::create_dir(&dir)
         .await
         .with_context(|| format!("Unable to create dir for problem {}.", problem_id))?;
 
-    let mut json = creds.clone();
-    json.insert("problem", problem_id.to_string());
-    let res = client
-        .get(format!("{}/judge/problem", url))
-        .header("Content-Type", "application/json")
-        .json(&json)
-        .send()
-        .await?;
-
-    match res.status() {
-        reqwest::StatusCode::OK => match res.json::<Problem>().await {
-            Ok(parsed) => {
-                fs::write(
-                    format!("problems/{}/input.txt", problem_id),
-                    &parsed.problem_input,
-                )
+    // Objects are read-only, which keeps the files locked when copied into job dirs
+    for (name, blob) in [("input", &manifest.input), ("output", &manifest.output)] {
+        let link = dir.join(blob.file_name(name));
+        let object = blob.object(root);
+        if fs::hard_link(&object, &link).await.is_err() {
+            fs::copy(&object, &link)
                 .await
-                .with_context(|| format!("Unable to write input for problem {}.", problem_id))?;
-
-                // Will make sure input is locked when copied into job dir
-                let mut perms = fs::metadata(format!("problems/{}/input.txt", problem_id))
-                    .await
-                    .with_context(|| {
-                        format!(
-                            "Unable to get perms on input.txt for problem {}.",
-                            problem_id
-                        )
-                    })?
-                    .permissions();
-                perms.set_readonly(true);
-                fs::set_permissions(format!("problems/{}/input.txt", problem_id), perms)
-                    .await
-                    .with_context(|| {
-                        format!(
-                            "Unable to set perms on input.txt for problem {}.",
-                            problem_id
-                        )
-                    })?;
-
-                fs::write(
-                    format!("problems/{}/output.txt", problem_id),
-                    &parsed.problem_output,
-                )
+                .with_context(|| format!("Unable to write {} for problem {}.", name, problem_id))?;
+        }
+    }
+    fs::write(dir.join("manifest.json"), serde_json::to_vec(&manifest)?)
+        .await
+        .with_context(|| format!("Unable to write manifest for problem {}.", problem_id))?;
+
+    swap_in(root, problem_id, &version).await
+}
+
+// Points `problems/<id>` at the new version by renaming a fresh symlink over it
+async fn swap_in(root: &Path, problem_id: i32, version: &str) -> Result<()> {
+    let current = problem_dir(root, problem_id);
+    let previous = fs::read_link(&current).await.ok();
+    // Problems cached before versions existed are plain dirs
+    if previous.is_none() && fs::try_exists(&current).await? {
+        fs::remove_dir_all(&current)
+            .await
+            .with_context(|| format!("Unable to clear dir for problem {}.", problem_id))?;
+    }
+
+    let link = root
+        .join("problems")
+        .join(format!("{}.tmp-{}", problem_id, version));
+    fs::symlink(Path::new("versions").join(version), &link)
+        .await
+        .with_context(|| format!("Unable to link problem {}.", problem_id))?;
+    fs::rename(&link, &current)
+        .await
+        .with_context(|| format!("Unable to swap in problem {}.", problem_id))?;
+
+    // Jobs still reading the old files keep them open after they are unlinked
+    if let Some(previous) = previous {
+        let _ = fs::remove_dir_all(root.join("problems").join(previous)).await;
+    }
+    Ok(())
+}
+
+// Records when a problem was last used, for evicting the least recently used first
+async fn touch(root: &Path, problem_id: i32) {
+    let path = problem_dir(root, problem_id).join("last_used");
+    if let Err(err) = fs::write(&path, Utc::now().to_rfc3339()).await {
+        error!("Unable to write {}: {}", path.display(), err);
+    }
+}
+
+/// Every problem in the cache, in no particular order.
+pub async fn entries(root: &Path) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut dirs = match fs::read_dir(root.join("problems")).await {
+        Ok(dirs) => dirs,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(entries),
+        Err(err) => return Err(err).context("Unable to read problems directory"),
+    };
+    while let Some(dir) = dirs.next_entry().await? {
+        // Skips objects/, versions/ and links that are being swapped in
+        let Ok(problem_id) = dir.file_name().to_string_lossy().parse::<i32>() else {
+            continue;
+        };
+        let Ok(manifest) = read_manifest(root, problem_id).await else {
+            continue;
+        };
+        let last_used = fs::read_to_string(problem_dir(root, problem_id).join("last_used"))
+            .await
+            .ok()
+            .and_then(|used| DateTime::parse_from_rfc3339(used.trim()).ok())
+            .map(|used| used.with_timezone(&Utc));
+        entries.push(Entry {
+            problem_id,
+            manifest,
+            last_used,
+        });
+    }
+    Ok(entries)
+}
+
+/// Evicts the least recently used problems that no running job needs until the
+/// store fits in `budget` bytes. Returns the ids of the evicted problems.
+pub async fn evict(root: &Path, budget: u64) -> Result<Vec<i32>> {
+    let _store = STORE.write().await;
+    let mut used = collect_garbage(root).await?;
+    let mut evicted = Vec::new();
+    if used <= budget {
+        return Ok(evicted);
+    }
+
+    let mut entries = entries(root).await?;
+    entries.sort_by_key(|entry| entry.last_used);
+    for entry in entries {
+        if used <= budget {
+            break;
+        }
+        if PINS.lock().unwrap().contains_key(&entry.problem_id) {
+            continue;
+        }
+        remove_entry(root, entry.problem_id).await?;
+        evicted.push(entry.problem_id);
+        used = collect_garbage(root).await?;
+    }
+    Ok(evicted)
+}
+
+/// Removes problems from the cache, whether or not anything is using them.
+pub async fn purge(root: &Path, problem_ids: &[i32]) -> Result<()> {
+    let _store = STORE.write().await;
+    for problem_id in problem_ids {
+        remove_entry(root, *problem_id).await?;
+    }
+    collect_garbage(root).await?;
+    Ok(())
+}
+
+async fn remove_entry(root: &Path, problem_id: i32) -> Result<()> {
+    let current = problem_dir(root, problem_id);
+    match fs::read_link(&current).await {
+        Ok(version) => {
+            fs::remove_file(&current).await?;
+            fs::remove_dir_all(root.join("problems").join(version))
                 .await
-                .with_context(|| format!("Unable to write output for problem {}.", problem_id))?;
-
-                // Will make sure output is locked when copied into job dir
-                let mut perms = fs::metadata(format!("problems/{}/output.txt", problem_id))
-                    .await
-                    .with_context(|| {
-                        format!(
-                            "Unable to get perms on output.txt for problem {}.",
-                            problem_id
-                        )
-                    })?
-                    .permissions();
-                perms.set_readonly(true);
-                fs::set_permissions(format!("problems/{}/output.txt", problem_id), perms)
-                    .await
-                    .with_context(|| {
-                        format!(
-                            "Unable to set perms on output.txt for problem {}.",
-                            problem_id
-                        )
-                    })?;
-
-                Ok(())
+                .with_context(|| format!("Unable to remove problem {}", problem_id))?;
+        }
+        Err(_) => fs::remove_dir_all(&current)
+            .await
+            .with_context(|| format!("Unable to remove problem {}", problem_id))?,
+    }
+    Ok(())
+}
+
+// Size of the store in bytes, including downloads in progress
+async fn stored(root: &Path) -> Result<u64> {
+    let mut used = 0;
+    let mut objects = match fs::read_dir(root.join(OBJECTS)).await {
+        Ok(objects) => objects,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err).context("Unable to read the problem store"),
+    };
+    while let Some(object) = objects.next_entry().await? {
+        used += object.metadata().await?.len();
+    }
+    Ok(used)
+}
+
+// Removes objects no problem links to, returning the size of the rest in bytes
+async fn collect_garbage(root: &Path) -> Result<u64> {
+    let referenced: HashSet<String> = entries(root)
+        .await?
+        .into_iter()
+        .flat_map(|entry| {
+            [
+                entry.manifest.input.object_name(),
+                entry.manifest.output.object_name(),
+            ]
+        })
+        .collect();
+
+    let mut used = 0;
+    let mut objects = match fs::read_dir(root.join(OBJECTS)).await {
+        Ok(objects) => objects,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err).context("Unable to read the problem store"),
+    };
+    while let Some(object) = objects.next_entry().await? {
+        let name = object.file_name().to_string_lossy().into_owned();
+        let size = object.metadata().await?.len();
+        // Downloads in progress only exist as tmp files
+        if name.starts_with("tmp-") || referenced.contains(&name) {
+            used += size;
+            continue;
+        }
+        info!("Evicting cached object {}", name);
+        fs::remove_file(object.path()).await?;
+    }
+    Ok(used)
+}
+
+/// Hashes a cached problem's files again, returning what doesn't match its manifest.
+pub async fn verify(root: &Path, entry: &Entry) -> Result<Vec<String>> {
+    let mut problems = Vec::new();
+    for (name, blob) in [
+        ("input", &entry.manifest.input),
+        ("output", &entry.manifest.output),
+    ] {
+        let path = problem_dir(root, entry.problem_id).join(blob.file_name(name));
+        let compressed = blob.compressed;
+        let found = match tokio::task::spawn_blocking(move || hash_file(&path, compressed)).await? {
+            Ok(found) => found,
+            Err(err) => {
+                problems.push(format!("{} is unreadable: {:#}", name, err));
+                continue;
             }
-            Err(_) => Err(anyhow!(Errors::ProblemFetchError)),
-        },
-        _other => Err(anyhow!(Errors::RavelError)),
+        };
+        if found.sha256 != blob.sha256 || found.md5 != blob.md5 || found.size != blob.size {
+            problems.push(format!(
+                "{} has sha256 {} ({} bytes) but the manifest expects {} ({} bytes)",
+                name, found.sha256, found.size, blob.sha256, blob.size
+            ));
+        }
+    }
+    Ok(problems)
+}
+
+// Hashes the uncompressed contents of a stored file
+fn hash_file(path: &Path, compressed: bool) -> Result<Blob> {
+    let file = std::fs::File::open(path)?;
+    let mut reader: Box<dyn Read> = if compressed {
+        Box::new(zstd::stream::read::Decoder::new(file)?)
+    } else {
+        Box::new(file)
+    };
+    let mut sha256 = Sha256::new();
+    let mut md5 = md5::Context::new();
+    let mut size = 0;
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        sha256.update(&buffer[..read]);
+        md5.consume(&buffer[..read]);
+        size += read as u64;
+    }
+    Ok(Blob {
+        sha256: format!("{:x}", sha256.finalize()),
+        md5: format!("{:x}", md5.compute()),
+        size,
+        compressed,
+    })
+}
+
+/// Copies a cached file into `dest`, decompressing it if it is stored compressed.
+pub async fn extract(root: &Path, problem_id: i32, part: Part, dest: &Path) -> Result<()> {
+    let manifest = read_manifest(root, problem_id).await?;
+    let (name, blob) = match part {
+        Part::Input => ("input", manifest.input),
+        Part::Output => ("output", manifest.output),
+    };
+    let path = problem_dir(root, problem_id).join(blob.file_name(name));
+    if !blob.compressed {
+        fs::copy(&path, dest).await?;
+        return Ok(());
+    }
+
+    let dest = dest.to_path_buf();
+    tokio::task::spawn_blocking(move || -> Result<()> {
+        let input = std::fs::File::open(&path)?;
+        let mut output = std::fs::File::create(&dest)?;
+        zstd::stream::copy_decode(input, &mut output)?;
+        // Read-only like the copies of uncompressed objects
+        let mut perms = output.metadata()?.permissions();
+        perms.set_readonly(true);
+        output.set_permissions(perms)?;
+        Ok(())
+    })
+    .await?
+    .with_context(|| format!("Unable to decompress {} of problem {}", name, problem_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::{FinishedSubmissions, Problem, Progress, Submission};
+    use async_trait::async_trait;
+
+    // Serves the same test data for every problem
+    struct FixedSource;
+
+    #[async_trait]
+    impl SubmissionSource for FixedSource {
+        async fn pending(&self, _judge: &str, _count: usize) -> Result<Vec<Submission>> {
+            Ok(Vec::new())
+        }
+
+        async fn problem(&self, _problem_id: i32) -> Result<Problem> {
+            Ok(Problem {
+                problem_input: String::from("2 3\n"),
+                problem_output: String::from("5\n"),
+            })
+        }
+
+        async fn report(&self, results: Vec<FinishedSubmissions>) -> Result<Vec<i32>> {
+            Ok(results.into_iter().map(|result| result.id).collect())
+        }
+
+        async fn progress(&self, _judge: &str, _id: i32, _progress: Progress) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("debussy-cache-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        root
+    }
+
+    #[tokio::test]
+    async fn download_with_empty_sums() {
+        let root = scratch("empty-sums");
+        cache_problem(&root, &FixedSource, 7, "", "", None, false)
+            .await
+            .unwrap();
+        let manifest = read_manifest(&root, 7).await.unwrap();
+        assert_eq!(manifest.input.md5, format!("{:x}", md5::compute("2 3\n")));
+        assert_eq!(manifest.output.md5, format!("{:x}", md5::compute("5\n")));
+        assert_eq!(
+            std::fs::read_to_string(problem_dir(&root, 7).join("input.txt")).unwrap(),
+            "2 3\n"
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn download_with_wrong_sums() {
+        let root = scratch("wrong-sums");
+        let input_sum = format!("{:x}", md5::compute("2 3\n"));
+        assert!(
+            cache_problem(&root, &FixedSource, 7, &input_sum, "0123", None, false)
+                .await
+                .is_err()
+        );
+        assert!(read_manifest(&root, 7).await.is_err());
+        std::fs::remove_dir_all(&root).unwrap();
     }
 }
@@ -0,0 +1,173 @@
+use crate::cache::{self, Entry};
+use crate::config::Config;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use clap::{Args, Subcommand};
+use serde::Serialize;
+
+#[derive(Args, Debug)]
+pub struct CacheArgs {
+    #[command(subcommand)]
+    pub action: CacheAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheAction {
+    /// List cached problems with their size and when they were last used
+    List,
+    /// Hash cached test data again and compare it to the manifests
+    Verify {
+        /// Problems to verify, every cached problem when empty
+        problems: Vec<i32>,
+    },
+    /// Remove problems from the cache, they are downloaded again when needed
+    Purge {
+        problems: Vec<i32>,
+        /// Remove every cached problem
+        #[arg(long, conflicts_with = "problems")]
+        all: bool,
+    },
+}
+
+// A cached problem as printed by `cache list --json`
+#[derive(Serialize, Debug)]
+struct Listed {
+    problem: i32,
+    // Bytes
+    size: u64,
+    last_used: Option<DateTime<Utc>>,
+}
+
+// A problem checked by `cache verify --json`, corrupt when there are problems
+#[derive(Serialize, Debug)]
+struct Verified {
+    problem: i32,
+    problems: Vec<String>,
+}
+
+/// Manages the problem cache in `problems/` of the configured work dir. Returns
+/// false when verification finds corrupt entries.
+pub async fn cache(args: CacheArgs, config: &Config, json: bool) -> Result<bool> {
+    let root = &config.paths.work_dir;
+    let mut entries = cache::entries(root).await?;
+    entries.sort_by_key(|entry| entry.problem_id);
+    match args.action {
+        CacheAction::List if json => {
+            let listed: Vec<Listed> = entries
+                .iter()
+                .map(|entry| Listed {
+                    problem: entry.problem_id,
+                    size: entry.size(),
+                    last_used: entry.last_used,
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&listed)?);
+            Ok(true)
+        }
+        CacheAction::List => {
+            list(&entries);
+            Ok(true)
+        }
+        CacheAction::Verify { problems } => {
+            let mut verified = Vec::new();
+            for entry in selected(&entries, &problems)? {
+                let problems = cache::verify(root, entry).await?;
+                if !json && problems.is_empty() {
+                    println!("ok       {}", entry.problem_id);
+                } else if !json {
+                    for problem in &problems {
+                        println!("CORRUPT  {} {}", entry.problem_id, problem);
+                    }
+                }
+                verified.push(Verified {
+                    problem: entry.problem_id,
+                    problems,
+                });
+            }
+            let corrupt = verified
+                .iter()
+                .filter(|verified| !verified.problems.is_empty())
+                .count();
+            if json {
+                println!("{}", serde_json::to_string_pretty(&verified)?);
+            } else if corrupt > 0 {
+                println!(
+                    "{} corrupt problems, purge them to download them again",
+                    corrupt
+                );
+            }
+            Ok(corrupt == 0)
+        }
+        CacheAction::Purge { problems, all } => {
+            let problems: Vec<i32> = if all {
+                entries.iter().map(|entry| entry.problem_id).collect()
+            } else if problems.is_empty() {
+                bail!("Name the problems to purge or pass --all");
+            } else {
+                selected(&entries, &problems)?
+                    .iter()
+                    .map(|entry| entry.problem_id)
+                    .collect()
+            };
+            cache::purge(root, &problems).await?;
+            if json {
+                println!("{}", serde_json::json!({ "purged": problems }));
+            } else {
+                println!("Purged {} problems", problems.len());
+            }
+            Ok(true)
+        }
+    }
+}
+
+fn list(entries: &[Entry]) {
+    println!("{:>10} {:>12}  last used", "problem", "size");
+    for entry in entries {
+        println!(
+            "{:>10} {:>12}  {}",
+            entry.problem_id,
+            format_size(entry.size()),
+            entry
+                .last_used
+                .map(|used| used.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or(String::from("never"))
+        );
+    }
+    let total: u64 = entries.iter().map(Entry::size).sum();
+    println!(
+        "{} problems, {} (shared files are counted once per problem)",
+        entries.len(),
+        format_size(total)
+    );
+}
+
+// Every entry when no problems are named, otherwise the named ones, which have to be cached
+fn selected<'a>(entries: &'a [Entry], problems: &[i32]) -> Result<Vec<&'a Entry>> {
+    if problems.is_empty() {
+        return Ok(entries.iter().collect());
+    }
+    problems
+        .iter()
+        .map(
+            |id| match entries.iter().find(|entry| entry.problem_id == *id) {
+                Some(entry) => Ok(entry),
+                None => bail!("Problem {} is not cached", id),
+            },
+        )
+        .collect()
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
@@ -0,0 +1,724 @@
+use crate::{resources, Languages};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, Subcommand, ValueEnum};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// Read when neither --config nor debussy_config names a file
+const DEFAULT_PATH: &str = "debussy.toml";
+
+/// Where the configuration comes from, and the settings that can be overridden
+/// on the command line.
+#[derive(Args, Debug, Default, Clone)]
+pub struct ConfigArgs {
+    // Set from the global --config option
+    #[arg(skip)]
+    pub config: Option<PathBuf>,
+    /// Most submissions judged at once
+    #[arg(long)]
+    pub max_jobs: Option<usize>,
+    /// Name this judge uses with the source
+    #[arg(long)]
+    pub judge_id: Option<String>,
+    /// Platform to take submissions from
+    #[arg(long, value_enum)]
+    pub source: Option<SourceKind>,
+    #[arg(long)]
+    pub ravel_url: Option<String>,
+    /// Docker API the judge containers run on
+    #[arg(long)]
+    pub docker_url: Option<String>,
+    /// Docker image submissions are judged in
+    #[arg(long)]
+    pub image: Option<String>,
+    /// Directory holding problems/ and jobs/
+    #[arg(long)]
+    pub work_dir: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct ConfigCommandArgs {
+    #[command(subcommand)]
+    pub action: ConfigAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigAction {
+    /// Load and validate the configuration and print the settings in effect
+    Check(ConfigArgs),
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Eq, PartialEq, Default, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    #[default]
+    Ravel,
+    Domjudge,
+}
+
+impl FromStr for SourceKind {
+    type Err = anyhow::Error;
+
+    fn from_str(source: &str) -> Result<Self> {
+        match source {
+            "ravel" => Ok(SourceKind::Ravel),
+            "domjudge" => Ok(SourceKind::Domjudge),
+            other => bail!("should be ravel or domjudge, not '{}'", other),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Eq, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum IntakeMode {
+    #[default]
+    Poll,
+    LongPoll,
+    Webhook,
+}
+
+impl FromStr for IntakeMode {
+    type Err = anyhow::Error;
+
+    fn from_str(mode: &str) -> Result<Self> {
+        match mode {
+            "poll" => Ok(IntakeMode::Poll),
+            "longpoll" => Ok(IntakeMode::LongPoll),
+            "webhook" => Ok(IntakeMode::Webhook),
+            other => bail!("should be poll, longpoll or webhook, not '{}'", other),
+        }
+    }
+}
+
+/// Every setting of the judge. Each one can be set in the config file, by the
+/// environment variable (or .env entry) named next to it, and some on the
+/// command line, with later ones taking precedence.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // judge_id, the hostname by default
+    pub judge_id: Option<String>,
+    // max_jobs
+    pub max_jobs: Option<usize>,
+    // source
+    pub source: SourceKind,
+    // lease_heartbeat, seconds between lease renewals
+    pub lease_heartbeat: u64,
+    // shutdown_timeout, seconds running jobs get to finish on shutdown
+    pub shutdown_timeout: u64,
+    pub ravel: RavelConfig,
+    pub domjudge: DomJudgeConfig,
+    pub docker: DockerConfig,
+    pub resources: ResourcesConfig,
+    pub intake: IntakeConfig,
+    pub cache: CacheConfig,
+    pub paths: PathsConfig,
+    // Only set in the config file, as [languages.<name>]
+    pub languages: HashMap<Languages, LanguageConfig>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RavelConfig {
+    // ravel_url
+    pub url: Option<String>,
+    // ravel_username and ravel_password, unless an api key is set
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // ravel_api_key
+    pub api_key: Option<String>,
+    // ravel_timeout, seconds, also used for DOMjudge
+    pub timeout: u64,
+    // ravel_retries
+    pub retries: u32,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct DomJudgeConfig {
+    // domjudge_url, the /api/v4 base
+    pub url: Option<String>,
+    // domjudge_username and domjudge_password
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DockerConfig {
+    // docker_url
+    pub url: String,
+    // judge_image
+    pub image: String,
+    // kill_timeout, seconds a container may run before it is killed
+    pub kill_timeout: u64,
+    // job_poll_interval, milliseconds between checks on running containers
+    pub poll_interval: u64,
+    // container_max_age, minutes before the janitor removes a container
+    pub container_max_age: u64,
+    // janitor_interval, seconds between janitor sweeps
+    pub janitor_interval: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResourcesConfig {
+    // reserved_cores, a cpu list like 0-1,4
+    pub reserved_cores: String,
+    // total_cores and total_memory (MB), the whole host by default
+    pub total_cores: Option<usize>,
+    pub total_memory: Option<u64>,
+    // job_cores and job_memory (MB)
+    pub job_cores: usize,
+    pub job_memory: u64,
+    // pin_cpus
+    pub pin_cpus: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct IntakeConfig {
+    // intake
+    pub mode: IntakeMode,
+    // poll_min and poll_max, seconds
+    pub poll_min: u64,
+    pub poll_max: u64,
+    // long_poll_wait, seconds
+    pub long_poll_wait: u64,
+    // webhook_addr and webhook_secret
+    pub webhook_addr: String,
+    pub webhook_secret: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    // cache_budget, MB
+    pub budget: Option<u64>,
+    // cache_compress
+    pub compress: bool,
+    // prefetch_interval, seconds
+    pub prefetch_interval: u64,
+    // status_addr
+    pub status_addr: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathsConfig {
+    // work_dir, where problems/ and jobs/ are kept
+    pub work_dir: PathBuf,
+    // state_dir, relative to work_dir
+    pub state_dir: PathBuf,
+}
+
+/// How submissions in one language are judged.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LanguageConfig {
+    // The submission's time limit is multiplied by this, for slower languages
+    pub time_multiplier: f64,
+}
+
+impl Default for LanguageConfig {
+    fn default() -> Self {
+        LanguageConfig {
+            time_multiplier: 1.0,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            judge_id: None,
+            max_jobs: None,
+            source: SourceKind::Ravel,
+            lease_heartbeat: 15,
+            shutdown_timeout: 600,
+            ravel: RavelConfig::default(),
+            domjudge: DomJudgeConfig::default(),
+            docker: DockerConfig::default(),
+            resources: ResourcesConfig::default(),
+            intake: IntakeConfig::default(),
+            cache: CacheConfig::default(),
+            paths: PathsConfig::default(),
+            languages: HashMap::new(),
+        }
+    }
+}
+
+impl Default for RavelConfig {
+    fn default() -> Self {
+        RavelConfig {
+            url: None,
+            username: None,
+            password: None,
+            api_key: None,
+            timeout: 30,
+            retries: 5,
+        }
+    }
+}
+
+impl Default for DockerConfig {
+    fn default() -> Self {
+        DockerConfig {
+            url: String::from("http://localhost:2375"),
+            image: String::from("reverie_test"),
+            kill_timeout: 600,
+            poll_interval: 1000,
+            container_max_age: 30,
+            janitor_interval: 60,
+        }
+    }
+}
+
+impl Default for ResourcesConfig {
+    fn default() -> Self {
+        ResourcesConfig {
+            reserved_cores: String::new(),
+            total_cores: None,
+            total_memory: None,
+            job_cores: 1,
+            job_memory: 1024,
+            // Off so judges upgraded in place keep scheduling containers the way they did
+            pin_cpus: false,
+        }
+    }
+}
+
+impl Default for IntakeConfig {
+    fn default() -> Self {
+        IntakeConfig {
+            mode: IntakeMode::Poll,
+            poll_min: 1,
+            poll_max: 30,
+            long_poll_wait: 30,
+            webhook_addr: String::from("0.0.0.0:8787"),
+            webhook_secret: None,
+        }
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            budget: None,
+            compress: false,
+            prefetch_interval: 300,
+            status_addr: None,
+        }
+    }
+}
+
+impl Default for PathsConfig {
+    fn default() -> Self {
+        PathsConfig {
+            work_dir: PathBuf::from("."),
+            state_dir: PathBuf::from("state/"),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config file, if there is one, and applies the environment and
+    /// `args` on top of it. Nothing is validated yet.
+    pub fn load(args: &ConfigArgs) -> Result<Self> {
+        let mut config = match Self::locate(args) {
+            Some(path) => Self::read(&path)?,
+            None => Config::default(),
+        };
+        config.apply_env()?;
+        config.apply_args(args);
+        Ok(config)
+    }
+
+    /// The config file `load` reads, if any.
+    pub fn locate(args: &ConfigArgs) -> Option<PathBuf> {
+        // The .env is optional now that there is a config file
+        let _ = dotenvy::dotenv();
+        args.config
+            .clone()
+            .or_else(|| dotenvy::var("debussy_config").ok().map(PathBuf::from))
+            .or_else(|| {
+                Path::new(DEFAULT_PATH)
+                    .exists()
+                    .then(|| PathBuf::from(DEFAULT_PATH))
+            })
+    }
+
+    /// The cache budget in bytes.
+    pub fn cache_budget(&self) -> Option<u64> {
+        self.cache.budget.map(|budget| budget * 1024 * 1024)
+    }
+
+    /// Time limit in milliseconds for a submission in `language`.
+    pub fn timeout(&self, language: Languages, timeout_ms: u64) -> u64 {
+        match self.languages.get(&language) {
+            Some(language) => (timeout_ms as f64 * language.time_multiplier).ceil() as u64,
+            None => timeout_ms,
+        }
+    }
+
+    /// Name this judge uses with the source. Judges sharing a ravel need distinct
+    /// ids, the hostname is used by default.
+    pub fn judge_id(&self) -> String {
+        self.judge_id
+            .clone()
+            .or_else(|| {
+                fs::read_to_string("/etc/hostname")
+                    .ok()
+                    .map(|name| name.trim().to_string())
+            })
+            .unwrap_or(String::from("debussy"))
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Unable to read config file {}", path.display()))?;
+        toml::from_str(&text)
+            .with_context(|| format!("Unable to parse config file {}", path.display()))
+    }
+
+    fn apply_env(&mut self) -> Result<()> {
+        env_option(&mut self.judge_id, "judge_id")?;
+        env_option(&mut self.max_jobs, "max_jobs")?;
+        env(&mut self.source, "source")?;
+        env(&mut self.lease_heartbeat, "lease_heartbeat")?;
+        env(&mut self.shutdown_timeout, "shutdown_timeout")?;
+
+        env_option(&mut self.ravel.url, "ravel_url")?;
+        env_option(&mut self.ravel.username, "ravel_username")?;
+        env_option(&mut self.ravel.password, "ravel_password")?;
+        env_option(&mut self.ravel.api_key, "ravel_api_key")?;
+        env(&mut self.ravel.timeout, "ravel_timeout")?;
+        env(&mut self.ravel.retries, "ravel_retries")?;
+
+        env_option(&mut self.domjudge.url, "domjudge_url")?;
+        env_option(&mut self.domjudge.username, "domjudge_username")?;
+        env_option(&mut self.domjudge.password, "domjudge_password")?;
+
+        env(&mut self.docker.url, "docker_url")?;
+        env(&mut self.docker.image, "judge_image")?;
+        env(&mut self.docker.kill_timeout, "kill_timeout")?;
+        env(&mut self.docker.poll_interval, "job_poll_interval")?;
+        env(&mut self.docker.container_max_age, "container_max_age")?;
+        env(&mut self.docker.janitor_interval, "janitor_interval")?;
+
+        env(&mut self.resources.reserved_cores, "reserved_cores")?;
+        env_option(&mut self.resources.total_cores, "total_cores")?;
+        env_option(&mut self.resources.total_memory, "total_memory")?;
+        env(&mut self.resources.job_cores, "job_cores")?;
+        env(&mut self.resources.job_memory, "job_memory")?;
+        env(&mut self.resources.pin_cpus, "pin_cpus")?;
+
+        env(&mut self.intake.mode, "intake")?;
+        env(&mut self.intake.poll_min, "poll_min")?;
+        env(&mut self.intake.poll_max, "poll_max")?;
+        env(&mut self.intake.long_poll_wait, "long_poll_wait")?;
+        env(&mut self.intake.webhook_addr, "webhook_addr")?;
+        env_option(&mut self.intake.webhook_secret, "webhook_secret")?;
+
+        env_option(&mut self.cache.budget, "cache_budget")?;
+        env(&mut self.cache.compress, "cache_compress")?;
+        env(&mut self.cache.prefetch_interval, "prefetch_interval")?;
+        env_option(&mut self.cache.status_addr, "status_addr")?;
+
+        env(&mut self.paths.work_dir, "work_dir")?;
+        env(&mut self.paths.state_dir, "state_dir")?;
+        Ok(())
+    }
+
+    fn apply_args(&mut self, args: &ConfigArgs) {
+        if args.max_jobs.is_some() {
+            self.max_jobs = args.max_jobs;
+        }
+        if args.judge_id.is_some() {
+            self.judge_id = args.judge_id.clone();
+        }
+        if let Some(source) = args.source {
+            self.source = source;
+        }
+        if args.ravel_url.is_some() {
+            self.ravel.url = args.ravel_url.clone();
+        }
+        if let Some(url) = &args.docker_url {
+            self.docker.url = url.clone();
+        }
+        if let Some(image) = &args.image {
+            self.docker.image = image.clone();
+        }
+        if let Some(work_dir) = &args.work_dir {
+            self.paths.work_dir = work_dir.clone();
+        }
+    }
+
+    /// Everything wrong with the configuration, as messages naming the setting.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        match self.max_jobs {
+            None => problems.push(String::from(
+                "max_jobs is not set, it limits how many submissions are judged at once",
+            )),
+            Some(0) => problems.push(String::from("max_jobs has to be at least 1")),
+            Some(_) => {}
+        }
+
+        match self.source {
+            SourceKind::Ravel => {
+                match &self.ravel.url {
+                    None => problems.push(String::from("ravel_url is not set")),
+                    Some(url) => check_url(&mut problems, "ravel_url", url),
+                }
+                if self.ravel.api_key.is_none() {
+                    if self.ravel.username.is_none() {
+                        problems.push(String::from(
+                            "ravel_username is not set, set it with ravel_password or set ravel_api_key",
+                        ));
+                    }
+                    if self.ravel.password.is_none() {
+                        problems.push(String::from(
+                            "ravel_password is not set, set it with ravel_username or set ravel_api_key",
+                        ));
+                    }
+                }
+            }
+            SourceKind::Domjudge => {
+                match &self.domjudge.url {
+                    None => problems.push(String::from("domjudge_url is not set")),
+                    Some(url) => check_url(&mut problems, "domjudge_url", url),
+                }
+                if self.domjudge.username.is_none() {
+                    problems.push(String::from("domjudge_username is not set"));
+                }
+                if self.domjudge.password.is_none() {
+                    problems.push(String::from("domjudge_password is not set"));
+                }
+            }
+        }
+        if self.ravel.timeout == 0 {
+            problems.push(String::from("ravel_timeout has to be at least 1 second"));
+        }
+        if self.ravel.retries == 0 {
+            problems.push(String::from("ravel_retries has to be at least 1"));
+        }
+
+        check_url(&mut problems, "docker_url", &self.docker.url);
+        if self.docker.image.trim().is_empty() {
+            problems.push(String::from("judge_image is empty"));
+        }
+        for (name, value) in [
+            ("kill_timeout", self.docker.kill_timeout),
+            ("job_poll_interval", self.docker.poll_interval),
+            ("container_max_age", self.docker.container_max_age),
+            ("janitor_interval", self.docker.janitor_interval),
+            ("lease_heartbeat", self.lease_heartbeat),
+            ("prefetch_interval", self.cache.prefetch_interval),
+            ("poll_min", self.intake.poll_min),
+        ] {
+            if value == 0 {
+                problems.push(format!("{} has to be greater than 0", name));
+            }
+        }
+        if self.docker.container_max_age * 60 < self.docker.kill_timeout {
+            problems.push(format!(
+                "container_max_age ({} minutes) is shorter than kill_timeout ({} seconds), so the janitor would remove running containers",
+                self.docker.container_max_age, self.docker.kill_timeout
+            ));
+        }
+
+        if let Err(err) = self.reserved_cores() {
+            problems.push(format!("reserved_cores {:#}", err));
+        }
+        if self.resources.job_cores == 0 {
+            problems.push(String::from("job_cores has to be at least 1"));
+        }
+        if self.resources.job_memory == 0 {
+            problems.push(String::from("job_memory has to be greater than 0"));
+        }
+        if self.resources.total_cores == Some(0) {
+            problems.push(String::from("total_cores has to be at least 1"));
+        }
+        if let Some(total_cores) = self.resources.total_cores {
+            if self.resources.job_cores > total_cores {
+                problems.push(format!(
+                    "job_cores ({}) is more than total_cores ({}), no job could ever start",
+                    self.resources.job_cores, total_cores
+                ));
+            }
+        }
+        if let Some(total_memory) = self.resources.total_memory {
+            if self.resources.job_memory > total_memory {
+                problems.push(format!(
+                    "job_memory ({} MB) is more than total_memory ({} MB), no job could ever start",
+                    self.resources.job_memory, total_memory
+                ));
+            }
+        }
+
+        if self.intake.poll_min > self.intake.poll_max {
+            problems.push(format!(
+                "poll_min ({}) is greater than poll_max ({})",
+                self.intake.poll_min, self.intake.poll_max
+            ));
+        }
+        if self.intake.mode == IntakeMode::LongPoll && self.intake.long_poll_wait == 0 {
+            problems.push(String::from("long_poll_wait has to be greater than 0"));
+        }
+        if self.intake.mode == IntakeMode::Webhook {
+            if self.intake.webhook_secret.is_none() {
+                problems.push(String::from(
+                    "intake is webhook but webhook_secret is not set",
+                ));
+            }
+            check_addr(&mut problems, "webhook_addr", &self.intake.webhook_addr);
+        }
+        if let Some(addr) = &self.cache.status_addr {
+            check_addr(&mut problems, "status_addr", addr);
+        }
+        if self.cache.budget == Some(0) {
+            problems.push(String::from("cache_budget has to be greater than 0"));
+        }
+
+        for (language, config) in &self.languages {
+            if !config.time_multiplier.is_finite() || config.time_multiplier <= 0.0 {
+                problems.push(format!(
+                    "languages.{:?}.time_multiplier has to be greater than 0",
+                    language
+                ));
+            }
+        }
+
+        if !self.paths.work_dir.is_dir() {
+            problems.push(format!(
+                "work_dir {} is not a directory",
+                self.paths.work_dir.display()
+            ));
+        }
+        problems
+    }
+
+    /// Fails with every problem with the configuration.
+    pub fn validate(&self) -> Result<()> {
+        let problems = self.problems();
+        if !problems.is_empty() {
+            bail!("Invalid configuration:\n  {}", problems.join("\n  "));
+        }
+        Ok(())
+    }
+
+    pub fn reserved_cores(&self) -> Result<Vec<usize>> {
+        if self.resources.reserved_cores.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        resources::parse_cpu_list(&self.resources.reserved_cores)
+            .map_err(|err| anyhow!("should be a cpu list like 0-1,4: {}", err))
+    }
+
+    // A copy that is safe to print
+    fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        for secret in [
+            &mut config.ravel.password,
+            &mut config.ravel.api_key,
+            &mut config.domjudge.password,
+            &mut config.intake.webhook_secret,
+        ] {
+            if secret.is_some() {
+                *secret = Some(String::from("<redacted>"));
+            }
+        }
+        config
+    }
+}
+
+// Replaces `field` with the environment variable `name` if it is set
+fn env<T: FromStr>(field: &mut T, name: &str) -> Result<()>
+where
+    T::Err: std::fmt::Display,
+{
+    if let Ok(value) = dotenvy::var(name) {
+        *field = value
+            .parse()
+            .map_err(|err| anyhow!("{} {}", name, describe(&value, err)))?;
+    }
+    Ok(())
+}
+
+fn env_option<T: FromStr>(field: &mut Option<T>, name: &str) -> Result<()>
+where
+    T::Err: std::fmt::Display,
+{
+    if let Ok(value) = dotenvy::var(name) {
+        *field = Some(
+            value
+                .parse()
+                .map_err(|err| anyhow!("{} {}", name, describe(&value, err)))?,
+        );
+    }
+    Ok(())
+}
+
+// Parse errors of our own enums already say what was expected
+fn describe(value: &str, err: impl std::fmt::Display) -> String {
+    let err = err.to_string();
+    if err.starts_with("should be") {
+        err
+    } else {
+        format!("has an invalid value '{}': {}", value, err)
+    }
+}
+
+fn check_url(problems: &mut Vec<String>, name: &str, url: &str) {
+    match Url::parse(url) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+        Ok(url) => problems.push(format!(
+            "{} should be an http or https url, not {}",
+            name,
+            url.scheme()
+        )),
+        Err(err) => problems.push(format!("{} '{}' is not a valid url: {}", name, url, err)),
+    }
+}
+
+fn check_addr(problems: &mut Vec<String>, name: &str, addr: &str) {
+    if addr.parse::<std::net::SocketAddr>().is_err() {
+        problems.push(format!(
+            "{} '{}' should be an ip and port like 0.0.0.0:8787",
+            name, addr
+        ));
+    }
+}
+
+/// Loads and validates the configuration, printing every problem with it or
+/// the settings in effect. Returns whether it is valid.
+pub fn check(args: ConfigArgs, json: bool) -> Result<bool> {
+    let config = Config::load(&args)?;
+    let problems = config.problems();
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({
+                "valid": problems.is_empty(),
+                "problems": problems,
+                "config": config.redacted(),
+            }))?
+        );
+        return Ok(problems.is_empty());
+    }
+    if !problems.is_empty() {
+        for problem in &problems {
+            println!("ERROR    {}", problem);
+        }
+        println!("{} problems found", problems.len());
+        return Ok(false);
+    }
+    print!("{}", toml::to_string(&config.redacted())?);
+    println!("# Configuration is valid");
+    Ok(true)
+}
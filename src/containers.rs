@@ -0,0 +1,174 @@
+use crate::config::Config;
+use crate::docker::{kill_container, list_containers, rm_container, ContainerSummary};
+use crate::janitor::{created_by, JUDGE_LABEL, SUBMISSION_LABEL};
+use anyhow::Result;
+use chrono::Utc;
+use clap::{Args, Subcommand};
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Args, Debug)]
+pub struct ContainersArgs {
+    #[command(subcommand)]
+    pub action: ContainersAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ContainersAction {
+    /// List the containers of every judge on the docker host
+    List,
+    /// Remove this judge's stopped containers
+    Clean {
+        /// Also kill and remove running containers, only safe while the judge is stopped
+        #[arg(long)]
+        running: bool,
+        /// Clean up after every judge on the docker host instead of just this one
+        #[arg(long)]
+        all_judges: bool,
+    },
+}
+
+/// A container some judge created, as printed by `containers list`.
+#[derive(Serialize, Debug)]
+pub struct JudgeContainer {
+    pub name: String,
+    pub judge: String,
+    pub submission: Option<i32>,
+    // Like running or exited
+    pub state: String,
+    // Seconds since it was created
+    pub age: i64,
+}
+
+impl JudgeContainer {
+    fn new(summary: ContainerSummary, now: i64) -> Self {
+        let labels = summary.labels.unwrap_or_default();
+        JudgeContainer {
+            name: summary
+                .names
+                .first()
+                .map(|name| name.trim_start_matches('/').to_string())
+                .unwrap_or_default(),
+            judge: labels.get(JUDGE_LABEL).cloned().unwrap_or_default(),
+            submission: labels.get(SUBMISSION_LABEL).and_then(|id| id.parse().ok()),
+            state: summary.state,
+            age: now - summary.created,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct Cleaned {
+    removed: Vec<String>,
+    failed: Vec<String>,
+}
+
+/// Lists or removes judge containers on the configured docker host. Returns
+/// false when a container couldn't be removed.
+pub async fn containers(args: ContainersArgs, config: &Config, json: bool) -> Result<bool> {
+    let url = config.docker.url.clone();
+    match args.action {
+        ContainersAction::List => {
+            let containers = judge_containers(None, &url).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&containers)?);
+            } else {
+                list(&containers);
+            }
+            Ok(true)
+        }
+        ContainersAction::Clean {
+            running,
+            all_judges,
+        } => {
+            let judge = (!all_judges).then(|| config.judge_id());
+            let mut cleaned = Cleaned {
+                removed: Vec::new(),
+                failed: Vec::new(),
+            };
+            for container in judge_containers(judge.as_deref(), &url).await? {
+                if container.state == "running" {
+                    if !running {
+                        continue;
+                    }
+                    let _ = kill_container(container.name.clone(), url.clone()).await;
+                }
+                match rm_container(container.name.clone(), url.clone()).await {
+                    Ok(()) => {
+                        if !json {
+                            println!("removed  {}", container.name);
+                        }
+                        cleaned.removed.push(container.name);
+                    }
+                    Err(err) => {
+                        if !json {
+                            println!("FAILED   {} {:#}", container.name, err);
+                        }
+                        cleaned.failed.push(container.name);
+                    }
+                }
+            }
+            if json {
+                println!("{}", serde_json::to_string_pretty(&cleaned)?);
+            } else {
+                println!("Removed {} containers", cleaned.removed.len());
+            }
+            Ok(cleaned.failed.is_empty())
+        }
+    }
+}
+
+// Containers created by `judge`, or by any judge when None
+async fn judge_containers(judge: Option<&str>, url: &str) -> Result<Vec<JudgeContainer>> {
+    let mut filters = HashMap::new();
+    filters.insert("label", vec![JUDGE_LABEL.to_string()]);
+    let now = Utc::now().timestamp();
+    let mut containers: Vec<JudgeContainer> = list_containers(filters, url.to_string())
+        .await?
+        .into_iter()
+        .filter(|summary| {
+            judge.is_none_or(|judge| {
+                summary
+                    .labels
+                    .as_ref()
+                    .is_some_and(|labels| created_by(labels, judge))
+            })
+        })
+        .map(|summary| JudgeContainer::new(summary, now))
+        .collect();
+    containers.sort_by(|a, b| a.judge.cmp(&b.judge).then(b.age.cmp(&a.age)));
+    Ok(containers)
+}
+
+fn list(containers: &[JudgeContainer]) {
+    println!(
+        "{:<32} {:<16} {:>10} {:<10} {:>8}",
+        "name", "judge", "submission", "state", "age"
+    );
+    for container in containers {
+        println!(
+            "{:<32} {:<16} {:>10} {:<10} {:>8}",
+            container.name,
+            container.judge,
+            container
+                .submission
+                .map(|id| id.to_string())
+                .unwrap_or(String::from("-")),
+            container.state,
+            format_age(container.age)
+        );
+    }
+    let running = containers
+        .iter()
+        .filter(|container| container.state == "running")
+        .count();
+    println!("{} containers, {} running", containers.len(), running);
+}
+
+fn format_age(seconds: i64) -> String {
+    match seconds {
+        ..60 => format!("{}s", seconds.max(0)),
+        60..3600 => format!("{}m", seconds / 60),
+        _ => format!("{}h{:02}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
@@ -1,7 +1,7 @@
 use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use serde_json;
 use std::collections::HashMap;
 
 #[derive(Serialize)]
@@ -18,6 +18,7 @@ pub struct ContainerOptions {
     pub network_disabled: bool,
     pub env: Option<Vec<String>>,
     pub volumes: Option<HashMap<String, HashMap<String, String>>>,
+    pub labels: Option<HashMap<String, String>>,
 }
 
 #[derive(Deserialize)]
@@ -25,6 +26,13 @@ pub struct ContainerOptions {
 pub struct ContainerState {
     pub exit_code: i32,
     pub running: bool,
+    #[serde(default)]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub finished_at: Option<DateTime<Utc>>,
+    // Whether the kernel killed a process for going over the memory limit
+    #[serde(default, rename = "OOMKilled")]
+    pub oom_killed: bool,
 }
 
 #[derive(Deserialize)]
@@ -33,11 +41,39 @@ pub struct Inspect {
     state: ContainerState,
 }
 
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct ContainerSummary {
+    pub names: Vec<String>,
+    // Unix timestamp
+    pub created: i64,
+    pub labels: Option<HashMap<String, String>>,
+    // Like running or exited
+    #[serde(default)]
+    pub state: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct Version {
+    pub version: String,
+    pub api_version: String,
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct HostConfig {
     pub binds: Option<Vec<String>>,
     pub auto_remove: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nano_cpus: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpuset_cpus: Option<String>,
+    // Most processes and threads the container may have at once
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pids_limit: Option<i64>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -63,6 +99,10 @@ pub enum DockerErrors {
     InspectContainerError,
     RemoveContainerError,
     CannotRemoveRunningContainer,
+    ListContainersError,
+    VersionError,
+    InspectImageError,
+    ArchiveError,
 }
 
 impl std::fmt::Display for DockerErrors {
@@ -77,6 +117,10 @@ impl std::fmt::Display for DockerErrors {
             Self::InspectContainerError => write!(f, "Error inspecting container"),
             Self::RemoveContainerError => write!(f, "Error removing container"),
             Self::CannotRemoveRunningContainer => write!(f, "Cannot remove a running contaienr"),
+            Self::ListContainersError => write!(f, "Error listing containers"),
+            Self::VersionError => write!(f, "Error reading the docker version"),
+            Self::InspectImageError => write!(f, "Error inspecting image"),
+            Self::ArchiveError => write!(f, "Error reading files from container"),
         }
     }
 }
@@ -153,7 +197,9 @@ pub async fn container_state(name: String, url: String) -> Result<ContainerState
         .send()
         .await?;
 
-    if response.status().as_u16() == 500 {
+    if response.status().as_u16() == 404 {
+        Err(anyhow!(DockerErrors::NoSuchContainer))
+    } else if response.status().as_u16() == 500 {
         let error = response.json::<DockerApiError>().await?.message;
         Err(anyhow!(DockerErrors::InspectContainerError).context(error))
     } else {
@@ -165,6 +211,14 @@ pub async fn container_state(name: String, url: String) -> Result<ContainerState
     }
 }
 
+/// Whether `err` says the container doesn't exist, rather than that docker couldn't answer.
+pub fn is_missing(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<DockerErrors>(),
+        Some(DockerErrors::NoSuchContainer)
+    )
+}
+
 pub async fn rm_container(name: String, url: String) -> Result<()> {
     let client = Client::new();
     let response = client
@@ -186,3 +240,75 @@ pub async fn rm_container(name: String, url: String) -> Result<()> {
         Err(anyhow!(DockerErrors::RemoveContainerError).context(error))
     }
 }
+
+pub async fn list_containers(
+    filters: HashMap<&str, Vec<String>>,
+    url: String,
+) -> Result<Vec<ContainerSummary>> {
+    let client = Client::new();
+    let response = client
+        .get(format!("{}/containers/json", url))
+        .query(&[
+            ("all", "true".to_string()),
+            ("filters", serde_json::to_string(&filters)?),
+        ])
+        .send()
+        .await?;
+
+    if response.status().is_success() {
+        Ok(response.json::<Vec<ContainerSummary>>().await?)
+    } else {
+        let error = response.json::<DockerApiError>().await?.message;
+        Err(anyhow!(DockerErrors::ListContainersError).context(error))
+    }
+}
+
+pub async fn version(url: String) -> Result<Version> {
+    let client = Client::new();
+    let response = client.get(format!("{}/version", url)).send().await?;
+
+    if response.status().is_success() {
+        Ok(response.json::<Version>().await?)
+    } else {
+        let error = response.json::<DockerApiError>().await?.message;
+        Err(anyhow!(DockerErrors::VersionError).context(error))
+    }
+}
+
+/// Whether `image` has been pulled or built on the docker host.
+pub async fn image_exists(image: String, url: String) -> Result<bool> {
+    let client = Client::new();
+    let response = client
+        .get(format!("{}/images/{}/json", url, image))
+        .send()
+        .await?;
+
+    if response.status().is_success() {
+        Ok(true)
+    } else if response.status().as_u16() == 404 {
+        Ok(false)
+    } else {
+        let error = response.json::<DockerApiError>().await?.message;
+        Err(anyhow!(DockerErrors::InspectImageError).context(error))
+    }
+}
+
+/// A tar archive of `path` inside the container, read through its mounts.
+pub async fn container_archive(name: String, path: String, url: String) -> Result<Vec<u8>> {
+    let client = Client::new();
+    let response = client
+        .get(format!("{}/containers/{}/archive", url, name))
+        .query(&[("path", path)])
+        .send()
+        .await?;
+
+    if response.status().is_success() {
+        Ok(response.bytes().await?.to_vec())
+    } else if response.status().as_u16() == 404 {
+        let error = response.json::<DockerApiError>().await?.message;
+        Err(anyhow!(DockerErrors::NoSuchContainer).context(error))
+    } else {
+        let error = response.json::<DockerApiError>().await?.message;
+        Err(anyhow!(DockerErrors::ArchiveError).context(error))
+    }
+}
@@ -0,0 +1,263 @@
+use crate::archive::{Archive, Compression};
+use crate::config::{Config, SourceKind};
+use crate::docker::{
+    self, container_archive, create_container, rm_container, ContainerOptions, HostConfig,
+};
+use crate::janitor::JUDGE_LABEL;
+use crate::local::{format_time, format_verdict, LocalJudge, ScratchDir};
+use crate::problem::LocalProblem;
+use crate::runner::PIDS_LIMIT;
+use crate::suite::{self, Case};
+use crate::{source, Languages};
+use anyhow::{anyhow, bail, Result};
+use clap::Args;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+
+// Where job dirs are mounted in the judge image
+const MOUNT: &str = "/usr/src/debussy";
+
+#[derive(Args, Debug)]
+pub struct DoctorArgs {
+    /// Only run the verdict suite in these languages
+    #[arg(short, long, value_enum)]
+    pub language: Vec<Languages>,
+    /// Skip the verdict suite, which runs a container for each program
+    #[arg(long)]
+    pub no_suite: bool,
+}
+
+/// The outcome of one check, as printed by `doctor`.
+#[derive(Serialize, Debug)]
+pub struct Check {
+    pub name: String,
+    pub passed: bool,
+    // What was found, or what went wrong
+    pub detail: String,
+}
+
+#[derive(Serialize, Debug)]
+struct Report {
+    passed: bool,
+    checks: Vec<Check>,
+    // Printed as JSON at the end rather than check by check
+    #[serde(skip)]
+    json: bool,
+}
+
+impl Report {
+    fn record(&mut self, name: &str, result: Result<String>) -> bool {
+        let check = Check {
+            name: name.to_string(),
+            passed: result.is_ok(),
+            detail: result.unwrap_or_else(|err| format!("{:#}", err)),
+        };
+        if !self.json {
+            println!(
+                "{:<8} {:<24} {}",
+                if check.passed { "ok" } else { "FAIL" },
+                check.name,
+                check.detail
+            );
+        }
+        self.checks.push(check);
+        self.checks.last().is_some_and(|check| check.passed)
+    }
+}
+
+/// Checks that the judge could serve with this configuration: the docker host
+/// answers, the judge image is there, job dirs can be mounted, the source
+/// accepts the credentials and programs with known verdicts get them. Returns
+/// whether every check passed.
+pub async fn doctor(args: DoctorArgs, config: &Config, json: bool) -> Result<bool> {
+    let mut report = Report {
+        passed: false,
+        checks: Vec::new(),
+        json,
+    };
+
+    let problems = config.problems();
+    report.record(
+        "config",
+        if problems.is_empty() {
+            Ok(String::from("Configuration is valid"))
+        } else {
+            Err(anyhow!(problems.join("; ")))
+        },
+    );
+
+    let name = match config.source {
+        SourceKind::Ravel => "ravel",
+        SourceKind::Domjudge => "domjudge",
+    };
+    let checked = match source::connect(config) {
+        Ok(source) => source.check(&config.judge_id()).await,
+        Err(err) => Err(err),
+    };
+    report.record(
+        name,
+        checked.map(|()| format!("Credentials accepted by {}", source_url(config))),
+    );
+
+    // Each docker check needs the ones before it to pass
+    let url = config.docker.url.clone();
+    let version = docker::version(url.clone())
+        .await
+        .map(|version| {
+            format!(
+                "Docker {} (API {}) at {}",
+                version.version, version.api_version, url
+            )
+        })
+        .map_err(|err| err.context(format!("Unable to reach docker at {}", url)));
+    let image = config.docker.image.clone();
+    let ready = report.record("docker", version)
+        && report.record(
+            "image",
+            match docker::image_exists(image.clone(), url.clone()).await {
+                Ok(true) => Ok(format!("Judge image {} is present", image)),
+                Ok(false) => Err(anyhow!(
+                    "Judge image {} is missing, pull or build it",
+                    image
+                )),
+                Err(err) => Err(err.context(format!("Unable to inspect judge image {}", image))),
+            },
+        );
+
+    if ready {
+        let scratch = ScratchDir::create()?;
+        let mounted = report.record("mounts", check_mounts(config, &scratch).await);
+        if mounted && !args.no_suite {
+            run_suite(&mut report, &scratch, config, &args.language).await?;
+        }
+    }
+
+    report.passed = report.checks.iter().all(|check| check.passed);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else if report.passed {
+        println!("Everything looks good");
+    } else {
+        let failed = report.checks.iter().filter(|check| !check.passed).count();
+        println!("{} checks failed", failed);
+    }
+    Ok(report.passed)
+}
+
+// Creates a container with a dir mounted the way job dirs are and reads a file
+// back through docker, which fails when docker can't see the judge's files
+async fn check_mounts(config: &Config, scratch: &ScratchDir) -> Result<String> {
+    let dir = scratch.path().join("mounts");
+    fs::create_dir_all(&dir)?;
+    let token = format!("{:016x}", rand::random::<u64>());
+    fs::write(dir.join("probe.txt"), &token)?;
+
+    let mut labels = HashMap::new();
+    labels.insert(JUDGE_LABEL.to_string(), config.judge_id());
+    let options = ContainerOptions {
+        image: config.docker.image.clone(),
+        host_config: HostConfig {
+            binds: Some(vec![format!("{}:{}", dir.display(), MOUNT)]),
+            auto_remove: false,
+            memory: None,
+            nano_cpus: None,
+            cpuset_cpus: None,
+            pids_limit: Some(PIDS_LIMIT),
+        },
+        tty: false,
+        attach_stdin: false,
+        attach_stdout: false,
+        attach_stderr: false,
+        open_stdin: false,
+        stdin_once: false,
+        network_disabled: true,
+        env: None,
+        volumes: None,
+        labels: Some(labels),
+    };
+    let name = format!("debussy_doctor_{}", std::process::id());
+    let url = config.docker.url.clone();
+    create_container(options, name.clone(), url.clone()).await?;
+    let archive =
+        container_archive(name.clone(), format!("{}/probe.txt", MOUNT), url.clone()).await;
+    let _ = rm_container(name, url).await;
+
+    let mut unpacked = Archive::new(Compression::None)?;
+    let mut pieces = unpacked.feed(&archive?)?;
+    pieces.extend(unpacked.finish()?);
+    let probe: Vec<u8> = pieces
+        .into_iter()
+        .filter(|(path, _)| path == "probe.txt")
+        .flat_map(|(_, bytes)| bytes)
+        .collect();
+    if probe != token.as_bytes() {
+        bail!(
+            "Docker sees different files in {} than the judge, is it running on another machine?",
+            dir.display()
+        );
+    }
+    Ok(format!("Job dirs are mounted at {}", MOUNT))
+}
+
+// Judges every program of the suite in `languages`, or all of them when empty
+async fn run_suite(
+    report: &mut Report,
+    scratch: &ScratchDir,
+    config: &Config,
+    languages: &[Languages],
+) -> Result<()> {
+    let dir = scratch.path().join("problem");
+    fs::create_dir_all(dir.join("tests"))?;
+    fs::write(
+        dir.join("limits.json"),
+        serde_json::json!({ "timeout": suite::TIMEOUT, "memory": suite::MEMORY }).to_string(),
+    )?;
+    fs::write(dir.join("tests/sum.in"), suite::INPUT)?;
+    fs::write(dir.join("tests/sum.ans"), suite::ANSWER)?;
+    let problem = LocalProblem::load(&dir)?;
+    let local = LocalJudge::new(&problem, suite::MEMORY, scratch, config).await?;
+
+    for case in &suite::CASES {
+        if !languages.is_empty() && !languages.contains(&case.language) {
+            continue;
+        }
+        let name = format!("{:?} {}", case.language, case.name).to_lowercase();
+        report.record(&name, judge_case(&local, case).await);
+    }
+    Ok(())
+}
+
+async fn judge_case(local: &LocalJudge, case: &Case) -> Result<String> {
+    let outcome = local.run(case.language, case.source, 0).await?;
+    let verdict = format_verdict(outcome.result);
+    match outcome.result {
+        // A hog has to hit the container's memory limit, not some limit inside it
+        Some(_) if case.oom_killed && !outcome.oom_killed => {
+            bail!(
+                "Got {} but the container wasn't killed for using too much memory",
+                verdict
+            )
+        }
+        Some(result) if case.expected.contains(&result) => {
+            Ok(format!("{} in {}", verdict, format_time(outcome.time)))
+        }
+        _ => bail!(
+            "Expected {} but got {}",
+            case.expected
+                .iter()
+                .map(|result| format!("{:?}", result))
+                .collect::<Vec<_>>()
+                .join(" or "),
+            verdict
+        ),
+    }
+}
+
+fn source_url(config: &Config) -> String {
+    match config.source {
+        SourceKind::Ravel => config.ravel.url.clone(),
+        SourceKind::Domjudge => config.domjudge.url.clone(),
+    }
+    .unwrap_or_default()
+}
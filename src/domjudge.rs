@@ -0,0 +1,427 @@
+use crate::error::Errors;
+use crate::runner::JobResult;
+use crate::source::SubmissionSource;
+use crate::source::{FinishedSubmissions, Problem, Progress, Submission};
+use crate::Languages;
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use reqwest::{Client, RequestBuilder, Response};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+use tracing::{error, warn};
+
+// A unit of work handed out by fetch-work, one test case of one submission
+#[derive(Deserialize, Debug)]
+struct JudgeTask {
+    judgetaskid: Value,
+    #[serde(rename = "type")]
+    kind: String,
+    submitid: Option<Value>,
+    jobid: Option<Value>,
+    testcase_id: Option<Value>,
+    testcase_hash: Option<String>,
+    compile_script_id: Option<String>,
+    run_config: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RunConfig {
+    // Seconds
+    time_limit: f64,
+    // Kilobytes
+    memory_limit: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct File {
+    filename: String,
+    content: String,
+}
+
+// Checksums of a cached test case, valid while DOMjudge reports the same hash for it
+struct Sums {
+    hash: String,
+    input: String,
+    output: String,
+}
+
+/// Judgehost client for DOMjudge's REST API. Each judge task becomes a submission
+/// with the task id as its id and the test case id as its problem.
+pub struct DomJudgeClient {
+    client: Client,
+    url: String,
+    username: String,
+    password: String,
+    // Hostname this judge registered under, set on the first fetch
+    registered: OnceCell<String>,
+    // Latest hash DOMjudge reported for each test case
+    hashes: Mutex<HashMap<i32, String>>,
+    sums: Mutex<HashMap<i32, Sums>>,
+}
+
+fn id(value: &Value) -> Option<i32> {
+    match value {
+        Value::Number(number) => number.as_i64().and_then(|id| i32::try_from(id).ok()),
+        Value::String(string) => string.parse().ok(),
+        _ => None,
+    }
+}
+
+fn language(compile_script: &str) -> Option<Languages> {
+    let compile_script = compile_script.to_lowercase();
+    // Java's scripts are named like java_javac_detect, which javascript's aren't
+    if compile_script == "java" || compile_script.starts_with("java_") {
+        Some(Languages::Java)
+    } else if compile_script.starts_with("py") {
+        Some(Languages::Python)
+    } else if compile_script.starts_with("cpp") || compile_script.starts_with("c++") {
+        Some(Languages::Cpp)
+    } else {
+        None
+    }
+}
+
+fn run_result(result: &FinishedSubmissions) -> &'static str {
+    match result.error {
+        _ if result.solved => "correct",
+        Some(JobResult::Correct) => "correct",
+        Some(JobResult::Wrong) => "wrong-answer",
+        Some(JobResult::TimelimitException) => "timelimit",
+        Some(JobResult::CompilerError) => "compiler-error",
+        Some(JobResult::RuntimeError) | Some(JobResult::IllegalImport) | None => "run-error",
+    }
+}
+
+// DOMjudge takes every output base64 encoded
+fn encoded(output: &Option<String>) -> String {
+    BASE64_STANDARD.encode(output.as_deref().unwrap_or_default())
+}
+
+// What the judge itself has to say about a run, shown to jury members
+fn system_output(result: &FinishedSubmissions) -> String {
+    match result.error {
+        _ if result.solved => String::from("Judged by debussy: correct"),
+        Some(error) => format!("Judged by debussy: {:?}", error),
+        None => String::from("Judged by debussy: the judge image exited without a verdict"),
+    }
+}
+
+impl DomJudgeClient {
+    pub fn new(url: String, username: String, password: String, timeout: Duration) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(timeout)
+            .connect_timeout(timeout)
+            .build()
+            .context("Unable to build DOMjudge http client")?;
+        Ok(DomJudgeClient {
+            client,
+            url,
+            username,
+            password,
+            registered: OnceCell::new(),
+            hashes: Mutex::new(HashMap::new()),
+            sums: Mutex::new(HashMap::new()),
+        })
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        let res = request
+            .basic_auth(&self.username, Some(&self.password))
+            .send()
+            .await
+            .map_err(|err| anyhow!(err).context(Errors::RavelError))?;
+        match res.status().as_u16() {
+            200..=299 => Ok(res),
+            401 | 403 => Err(anyhow!(Errors::Unauthorized)),
+            status => Err(anyhow!(Errors::UnexpectedStatus(status))),
+        }
+    }
+
+    async fn register(&self, judge: &str) -> Result<()> {
+        self.registered
+            .get_or_try_init(|| async {
+                self.send(
+                    self.client
+                        .post(format!("{}/judgehosts", self.url))
+                        .form(&[("hostname", judge)]),
+                )
+                .await
+                .context("Unable to register judgehost with DOMjudge")?;
+                Ok::<String, anyhow::Error>(judge.to_string())
+            })
+            .await?;
+        Ok(())
+    }
+
+    // Tells DOMjudge a task can't be judged here, so it stops waiting on this judgehost
+    async fn internal_error(
+        &self,
+        task_id: Option<i32>,
+        description: &str,
+        disabled: Value,
+    ) -> Result<()> {
+        let mut form = vec![
+            ("description", description.to_string()),
+            ("judgehostlog", BASE64_STANDARD.encode(description)),
+            ("disabled", disabled.to_string()),
+        ];
+        if let Some(task_id) = task_id {
+            form.push(("judgetaskid", task_id.to_string()));
+        }
+        self.send(
+            self.client
+                .post(format!("{}/judgehosts/internal-error", self.url))
+                .form(&form),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn files(&self, kind: &str, id: i32) -> Result<Vec<File>> {
+        self.send(
+            self.client
+                .get(format!("{}/judgehosts/get_files/{}/{}", self.url, kind, id)),
+        )
+        .await
+        .with_context(|| format!("Unable to fetch {} {} from DOMjudge", kind, id))?
+        .json::<Vec<File>>()
+        .await
+        .map_err(|_| anyhow!(Errors::ProblemFetchError))
+    }
+
+    async fn decoded(&self, kind: &str, id: i32) -> Result<HashMap<String, String>> {
+        let mut decoded = HashMap::new();
+        for file in self.files(kind, id).await? {
+            let content = BASE64_STANDARD
+                .decode(file.content)
+                .with_context(|| format!("Invalid base64 in {} {}", kind, id))?;
+            decoded.insert(
+                file.filename,
+                String::from_utf8_lossy(&content).into_owned(),
+            );
+        }
+        Ok(decoded)
+    }
+
+    async fn submission(&self, task: JudgeTask) -> Result<Option<Submission>> {
+        // Only test case runs are judged here, other task types are skipped
+        if task.kind != "judging_run" {
+            return Ok(None);
+        }
+        let task_id = id(&task.judgetaskid).context("Judge task without an id")?;
+        let submit_id = task
+            .submitid
+            .as_ref()
+            .and_then(id)
+            .context("Judge task without a submission")?;
+        let testcase = task
+            .testcase_id
+            .as_ref()
+            .and_then(id)
+            .context("Judge task without a test case")?;
+        let language = task
+            .compile_script_id
+            .as_deref()
+            .and_then(language)
+            .with_context(|| format!("Unsupported language {:?}", task.compile_script_id))?;
+        let config: RunConfig = serde_json::from_str(task.run_config.as_deref().unwrap_or("{}"))
+            .context("Unable to parse run config")?;
+
+        let source = self.decoded("source", submit_id).await?;
+        let content = source
+            .into_values()
+            .next()
+            .with_context(|| format!("Submission {} has no source files", submit_id))?;
+
+        let hash = task.testcase_hash.unwrap_or_default();
+        self.hashes.lock().unwrap().insert(testcase, hash.clone());
+        // Unknown sums never match the cache, so the test case gets downloaded
+        let (input_sum, output_sum) = match self.sums.lock().unwrap().get(&testcase) {
+            Some(sums) if sums.hash == hash => (sums.input.clone(), sums.output.clone()),
+            _ => (String::new(), String::new()),
+        };
+
+        Ok(Some(Submission {
+            id: task_id,
+            language,
+            content,
+            problem: testcase,
+            input_sum,
+            output_sum,
+            timeout: config.time_limit.ceil() as i32,
+            timeout_ms: Some((config.time_limit * 1000.0).round() as u64),
+            memory_limit: config.memory_limit.map(|kb| kb / 1024),
+            judging: task.jobid.as_ref().and_then(id),
+        }))
+    }
+}
+
+#[async_trait]
+impl SubmissionSource for DomJudgeClient {
+    async fn pending(&self, judge: &str, count: usize) -> Result<Vec<Submission>> {
+        self.register(judge).await?;
+        let tasks = self
+            .send(
+                self.client
+                    .post(format!("{}/judgehosts/fetch-work", self.url))
+                    .form(&[("hostname", judge), ("max_batchsize", &count.to_string())]),
+            )
+            .await
+            .context("Unable to fetch work from DOMjudge")?
+            .json::<Vec<JudgeTask>>()
+            .await
+            .map_err(|_| anyhow!(Errors::SubmissionFetchError))?;
+
+        let mut submissions = Vec::new();
+        for task in tasks {
+            let task_id = id(&task.judgetaskid);
+            let script = task.compile_script_id.clone();
+            let err = match self.submission(task).await {
+                Ok(Some(submission)) => {
+                    submissions.push(submission);
+                    continue;
+                }
+                Ok(None) => continue,
+                Err(err) => err,
+            };
+            error!("Unable to judge DOMjudge task {:?}: {:#}", task_id, err);
+            // Languages this judge can't run are disabled so DOMjudge stops
+            // handing them out, anything else takes this judgehost out of rotation
+            let disabled = match script {
+                Some(script) if language(&script).is_none() => {
+                    serde_json::json!({ "kind": "executable", "execid": script })
+                }
+                _ => serde_json::json!({ "kind": "judgehost", "hostname": judge }),
+            };
+            if let Err(err) = self
+                .internal_error(task_id, &format!("{:#}", err), disabled)
+                .await
+            {
+                error!(
+                    "Unable to report DOMjudge task {:?} as an internal error: {:#}",
+                    task_id, err
+                );
+            }
+        }
+        Ok(submissions)
+    }
+
+    async fn problem(&self, problem_id: i32) -> Result<Problem> {
+        let mut files = self.decoded("testcase", problem_id).await?;
+        let problem = Problem {
+            problem_input: files
+                .remove("input")
+                .with_context(|| format!("Test case {} has no input", problem_id))?,
+            problem_output: files
+                .remove("output")
+                .with_context(|| format!("Test case {} has no output", problem_id))?,
+        };
+
+        let hash = self
+            .hashes
+            .lock()
+            .unwrap()
+            .get(&problem_id)
+            .cloned()
+            .unwrap_or_default();
+        self.sums.lock().unwrap().insert(
+            problem_id,
+            Sums {
+                hash,
+                input: format!("{:x}", md5::compute(&problem.problem_input)),
+                output: format!("{:x}", md5::compute(&problem.problem_output)),
+            },
+        );
+        Ok(problem)
+    }
+
+    async fn report(&self, results: Vec<FinishedSubmissions>) -> Result<Vec<i32>> {
+        let hostname = self
+            .registered
+            .get()
+            .context("Judgehost is not registered with DOMjudge")?;
+
+        let mut recorded = Vec::new();
+        for result in results {
+            let outcome = run_result(&result);
+            let request = if outcome == "compiler-error" {
+                // Resending would never get further, so the result is dropped
+                let Some(judging) = result.judging else {
+                    error!(
+                        "Dropping the compile error of DOMjudge task {}, its judging is unknown",
+                        result.id
+                    );
+                    recorded.push(result.id);
+                    continue;
+                };
+                self.client
+                    .put(format!(
+                        "{}/judgehosts/update-judging/{}/{}",
+                        self.url, hostname, judging
+                    ))
+                    .form(&[
+                        ("compile_success", String::from("0")),
+                        ("output_compile", encoded(&result.compiler)),
+                    ])
+            } else {
+                // Seconds, the image's own measurement when it kept one
+                let runtime = result.runtime.unwrap_or_default() as f64 / 1000.0;
+                self.client
+                    .post(format!(
+                        "{}/judgehosts/add-judging-run/{}/{}",
+                        self.url, hostname, result.id
+                    ))
+                    .form(&[
+                        ("runresult", outcome.to_string()),
+                        ("runtime", format!("{:.3}", runtime)),
+                        ("output_run", encoded(&result.output)),
+                        ("output_error", encoded(&result.errors)),
+                        (
+                            "output_system",
+                            BASE64_STANDARD.encode(system_output(&result)),
+                        ),
+                    ])
+            };
+
+            match self.send(request).await {
+                Ok(_) => recorded.push(result.id),
+                Err(err) => warn!("Unable to report DOMjudge task {}: {:#}", result.id, err),
+            }
+        }
+        Ok(recorded)
+    }
+
+    // DOMjudge has no progress reporting for judgehosts
+    async fn progress(&self, _judge: &str, _id: i32, _progress: Progress) -> Result<()> {
+        Ok(())
+    }
+
+    // Answers with the user the credentials belong to
+    async fn check(&self, _judge: &str) -> Result<()> {
+        self.send(self.client.get(format!("{}/user", self.url)))
+            .await
+            .context("Unable to reach DOMjudge")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn languages() {
+        assert_eq!(language("java_javac_detect"), Some(Languages::Java));
+        assert_eq!(language("java"), Some(Languages::Java));
+        assert_eq!(language("py3"), Some(Languages::Python));
+        assert_eq!(language("cpp"), Some(Languages::Cpp));
+        assert_eq!(language("javascript"), None);
+        assert_eq!(language("js"), None);
+        assert_eq!(language("kotlin"), None);
+    }
+}
@@ -1,14 +1,24 @@
 #[derive(Debug)]
 pub enum Errors {
     RavelError,
+    Unauthorized,
+    UnexpectedStatus(u16),
     SubmissionFetchError,
     ProblemFetchError,
+    ProblemListError,
+    LeaseFetchError,
+    LoginError,
+    AcknowledgementError,
 }
 
 impl std::fmt::Display for Errors {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::RavelError => write!(f, "Error communicating to ravel"),
+            Self::Unauthorized => write!(f, "Ravel rejected the judge's credentials"),
+            Self::UnexpectedStatus(status) => {
+                write!(f, "Ravel responded with unexpected status {}", status)
+            }
             Self::SubmissionFetchError => write!(
                 f,
                 "Unable to fetch submissions from ravel. Response did not match type of input."
@@ -17,6 +27,22 @@ impl std::fmt::Display for Errors {
                 f,
                 "Unable to fetch problem in/out from ravel. Response did not match type of input."
             ),
+            Self::ProblemListError => write!(
+                f,
+                "Unable to fetch active problems from ravel. Response did not match type of input."
+            ),
+            Self::LeaseFetchError => write!(
+                f,
+                "Unable to renew leases on ravel. Response did not match type of input."
+            ),
+            Self::LoginError => write!(
+                f,
+                "Unable to log in to ravel. Response did not match type of input."
+            ),
+            Self::AcknowledgementError => write!(
+                f,
+                "Unable to read acknowledged results from ravel. Response did not match type of input."
+            ),
         }
     }
 }
@@ -0,0 +1,227 @@
+use crate::ravel::Submissions;
+use crate::scheduler::{Job, Judge, Mode};
+use crate::source::Submission;
+use anyhow::{Context, Result};
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::routing::post;
+use axum::{Json, Router};
+use serde::Serialize;
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+use tokio::sync::mpsc::error::{SendError, TrySendError};
+use tokio::sync::mpsc::Sender;
+use tracing::{error, info};
+
+// How new submissions reach the judge
+#[derive(Debug, Clone)]
+pub enum Intake {
+    // Poll ravel, backing off from `min` to `max` while there is nothing to judge
+    Poll {
+        min: Duration,
+        max: Duration,
+    },
+    // Ravel holds each claim open for up to `wait` until submissions arrive
+    LongPoll {
+        wait: Duration,
+    },
+    // Ravel posts submissions to `addr`, with a slow poll as a safety net
+    Webhook {
+        addr: String,
+        secret: String,
+        poll: Duration,
+    },
+}
+
+pub async fn run(judge: Judge, queue: Sender<Job>, intake: Intake) {
+    match intake {
+        Intake::Poll { min, max } => poll(judge, queue, min, max).await,
+        Intake::LongPoll { wait } => long_poll(judge, queue, wait).await,
+        Intake::Webhook {
+            addr,
+            secret,
+            poll: every,
+        } => {
+            tokio::spawn(poll(judge.clone(), queue.clone(), every, every));
+            if let Err(err) = serve_webhook(judge, queue, &addr, secret).await {
+                error!("Submission webhook stopped: {:#}", err);
+            }
+        }
+    }
+}
+
+// Whether to keep fetching, returns None once the judge is shutting down
+fn fetching(judge: &Judge) -> Option<bool> {
+    let mode = *judge.mode.borrow();
+    match mode {
+        Mode::Serving => Some(true),
+        Mode::Draining => Some(false),
+        _ => None,
+    }
+}
+
+// Queues every submission the judge doesn't already have, returns false once
+// the queue has been closed. Submissions that can't be queued are handed back.
+async fn enqueue(judge: &Judge, queue: &Sender<Job>, submissions: Vec<Submission>) -> bool {
+    let mut submissions = submissions.into_iter();
+    while let Some(submission) = submissions.next() {
+        if let Some(job) = judge.admit(submission) {
+            if let Err(SendError(job)) = queue.send(job).await {
+                error!("Work queue has been closed, no longer taking submissions");
+                // The rest were leased too, but leave alone the ones already being judged
+                let active = judge.active.lock().unwrap().clone();
+                let unqueued: Vec<i32> = std::iter::once(job.submission.id)
+                    .chain(
+                        submissions
+                            .map(|submission| submission.id)
+                            .filter(|id| !active.contains(id)),
+                    )
+                    .collect();
+                judge.give_up(&unqueued).await;
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Leases as many submissions as fit in the work queue. The interval doubles up
+/// to `max` every time ravel has nothing and drops back to `min` once it does.
+async fn poll(judge: Judge, queue: Sender<Job>, min: Duration, max: Duration) {
+    let mut interval = min;
+    loop {
+        tokio::time::sleep(interval).await;
+        match fetching(&judge) {
+            Some(true) => {}
+            Some(false) => continue,
+            None => {
+                info!("Shutting down, no longer fetching submissions");
+                return;
+            }
+        }
+        let count = queue.capacity();
+        if count == 0 {
+            interval = min;
+            continue;
+        }
+        match judge.source.pending(&judge.judge_id, count).await {
+            Ok(subs) if subs.is_empty() => interval = (interval * 2).min(max),
+            Ok(subs) => {
+                interval = min;
+                if !enqueue(&judge, &queue, subs).await {
+                    return;
+                }
+            }
+            Err(err) => {
+                error!("Unable to fetch submissions with error: {}", err);
+                interval = (interval * 2).min(max);
+            }
+        }
+    }
+}
+
+async fn long_poll(judge: Judge, queue: Sender<Job>, wait: Duration) {
+    loop {
+        match fetching(&judge) {
+            Some(true) => {}
+            Some(false) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+            None => {
+                info!("Shutting down, no longer fetching submissions");
+                return;
+            }
+        }
+        // Wait for a free slot in the queue rather than claiming work we can't start
+        let permit = match queue.reserve().await {
+            Ok(permit) => permit,
+            Err(_) => return,
+        };
+        let count = queue.capacity() + 1;
+        drop(permit);
+
+        match judge
+            .source
+            .pending_wait(&judge.judge_id, count, wait)
+            .await
+        {
+            Ok(subs) => {
+                if !enqueue(&judge, &queue, subs).await {
+                    return;
+                }
+            }
+            Err(err) => {
+                error!("Unable to fetch submissions with error: {}", err);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Webhook {
+    judge: Judge,
+    queue: Sender<Job>,
+    secret: String,
+}
+
+#[derive(Serialize, Debug)]
+struct Accepted {
+    accepted: Vec<i32>,
+}
+
+async fn serve_webhook(judge: Judge, queue: Sender<Job>, addr: &str, secret: String) -> Result<()> {
+    let app = Router::new()
+        .route("/submissions", post(receive))
+        .with_state(Webhook {
+            judge,
+            queue,
+            secret,
+        });
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Unable to listen on {}", addr))?;
+    info!("Accepting submissions on http://{}/submissions", addr);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+/// Takes the submissions ravel pushes to this judge, answering with the ids it
+/// queued. Ravel keeps anything not accepted pending and can push it again.
+async fn receive(
+    State(webhook): State<Webhook>,
+    headers: HeaderMap,
+    Json(body): Json<Submissions>,
+) -> Result<Json<Accepted>, StatusCode> {
+    let authorized = headers
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| bool::from(token.as_bytes().ct_eq(webhook.secret.as_bytes())));
+    if !authorized {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    if fetching(&webhook.judge) != Some(true) {
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    let mut accepted = Vec::new();
+    for submission in body.submissions {
+        let id = submission.id;
+        let Some(job) = webhook.judge.admit(submission) else {
+            // Already queued or running here
+            accepted.push(id);
+            continue;
+        };
+        match webhook.queue.try_send(job) {
+            Ok(()) => accepted.push(id),
+            Err(TrySendError::Full(_)) => webhook.judge.release(id),
+            Err(TrySendError::Closed(_)) => {
+                webhook.judge.release(id);
+                return Err(StatusCode::SERVICE_UNAVAILABLE);
+            }
+        }
+    }
+    Ok(Json(Accepted { accepted }))
+}
@@ -0,0 +1,147 @@
+use crate::cache;
+use crate::docker::{kill_container, list_containers, rm_container};
+use crate::scheduler::Judge;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::{error, info};
+
+// Labels put on every container the judge creates, the judge label holds the judge id
+// so judges sharing a docker host leave each other's containers alone
+pub const JUDGE_LABEL: &str = "debussy.judge";
+pub const SUBMISSION_LABEL: &str = "debussy.submission";
+// What judges put in the judge label before it held their id
+const LEGACY_JUDGE: &str = "true";
+
+/// Whether `judge` created a container with these labels. Containers from before
+/// the label held the judge id count as every judge's.
+pub fn created_by(labels: &HashMap<String, String>, judge: &str) -> bool {
+    labels
+        .get(JUDGE_LABEL)
+        .is_some_and(|value| value == judge || value == LEGACY_JUDGE)
+}
+
+/// Sweeps for orphaned containers and job dirs at startup and then every `every`.
+pub async fn run(judge: Judge, every: Duration) {
+    let mut interval = tokio::time::interval(every);
+    loop {
+        interval.tick().await;
+        sweep(&judge).await;
+    }
+}
+
+/// Removes judge containers, and unlabelled `reverie_*` containers, that don't
+/// belong to an active job or are older than container_max_age, along with job
+/// dirs that don't belong to an active job, and evicts problems once the cache is
+/// over budget.
+pub async fn sweep(judge: &Judge) {
+    let config = judge.config();
+    let url = config.docker.url.clone();
+    let max_age = Duration::from_secs(config.docker.container_max_age * 60);
+    let now = Utc::now().timestamp();
+    let stale = |id: Option<i32>, created: i64| {
+        let active = id.is_some_and(|id| judge.active.lock().unwrap().contains(&id));
+        let expired = now - created > max_age.as_secs() as i64;
+        match (active, expired) {
+            (true, false) => None,
+            (true, true) => Some("expired"),
+            (false, _) => Some("orphaned"),
+        }
+    };
+    let mut removals = Vec::new();
+
+    let mut filters = HashMap::new();
+    filters.insert("label", vec![JUDGE_LABEL.to_string()]);
+    match list_containers(filters, url.clone()).await {
+        Ok(containers) => {
+            for container in containers {
+                let labels = container.labels.unwrap_or_default();
+                if !created_by(&labels, &judge.judge_id) {
+                    continue;
+                }
+                let Some(name) = container.names.first() else {
+                    continue;
+                };
+                let id = labels
+                    .get(SUBMISSION_LABEL)
+                    .and_then(|id| id.parse::<i32>().ok());
+                if let Some(reason) = stale(id, container.created) {
+                    removals.push((name.trim_start_matches('/').to_string(), reason));
+                }
+            }
+        }
+        Err(err) => error!("Unable to list judge containers: {}", err),
+    }
+
+    // Containers created before judges labelled them are only known by their name
+    let mut filters = HashMap::new();
+    filters.insert("name", vec![String::from("reverie_")]);
+    match list_containers(filters, url.clone()).await {
+        Ok(containers) => {
+            for container in containers {
+                let labelled = container
+                    .labels
+                    .as_ref()
+                    .is_some_and(|labels| labels.contains_key(JUDGE_LABEL));
+                if labelled {
+                    continue;
+                }
+                let Some(name) = container.names.first() else {
+                    continue;
+                };
+                let name = name.trim_start_matches('/');
+                // Docker matches names by substring, so skip anything not named like a job
+                let Some(id) = name
+                    .strip_prefix("reverie_")
+                    .and_then(|id| id.parse::<i32>().ok())
+                else {
+                    continue;
+                };
+                if let Some(reason) = stale(Some(id), container.created) {
+                    removals.push((name.to_string(), reason));
+                }
+            }
+        }
+        Err(err) => error!("Unable to list unlabelled containers: {}", err),
+    }
+
+    for (name, reason) in removals {
+        info!("Removing {} container {}", reason, name);
+        let _ = kill_container(name.clone(), url.clone()).await;
+        if let Err(err) = rm_container(name.clone(), url.clone()).await {
+            error!("Unable to remove container {}: {}", name, err)
+        }
+    }
+
+    match tokio::fs::read_dir("./jobs").await {
+        Ok(mut dirs) => {
+            while let Ok(Some(dir)) = dirs.next_entry().await {
+                let active = dir
+                    .file_name()
+                    .to_string_lossy()
+                    .parse::<i32>()
+                    .is_ok_and(|id| judge.active.lock().unwrap().contains(&id));
+                if !active {
+                    info!("Removing stale job dir {}", dir.path().display());
+                    if let Err(err) = tokio::fs::remove_dir_all(dir.path()).await {
+                        error!("Unable to remove job dir {}: {}", dir.path().display(), err)
+                    }
+                }
+            }
+        }
+        Err(err) => error!("Unable to read jobs directory: {}", err),
+    }
+
+    if let Some(budget) = config.cache_budget() {
+        match cache::evict(&config.paths.work_dir, budget).await {
+            Ok(evicted) if !evicted.is_empty() => {
+                info!(
+                    "Evicted problems {:?} to stay within the cache budget",
+                    evicted
+                )
+            }
+            Ok(_) => {}
+            Err(err) => error!("Unable to evict problems from the cache: {}", err),
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use anyhow::{bail, Result};
+
+// Where the decoder is within the top level object
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum State {
+    // Before the opening brace
+    Start,
+    // Expecting a key or the closing brace
+    Key,
+    // Inside a key
+    KeyString,
+    Colon,
+    Value,
+    // Inside a string value, which is streamed out
+    StringValue,
+    // Inside a non-string value, which is skipped
+    Skip,
+    // Expecting a comma or the closing brace
+    Next,
+    Done,
+}
+
+/// Decodes the string fields of a JSON object as its bytes arrive, so large
+/// values never have to be held in memory. Other fields are skipped.
+pub struct StringFields {
+    state: State,
+    key: Vec<u8>,
+    // Escape sequence split across chunks, starting with the backslash
+    escape: Vec<u8>,
+    // High half of a \u surrogate pair waiting for its low half
+    surrogate: Option<u16>,
+    // Nesting and string state of a skipped value
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl StringFields {
+    pub fn new() -> Self {
+        StringFields {
+            state: State::Start,
+            key: Vec::new(),
+            escape: Vec::new(),
+            surrogate: None,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+        }
+    }
+
+    /// Feeds the next chunk of the body, returning the decoded pieces of string
+    /// fields found in it as (key, bytes).
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<(String, Vec<u8>)>> {
+        let mut pieces: Vec<(String, Vec<u8>)> = Vec::new();
+        let mut value = Vec::new();
+        for &byte in chunk {
+            match self.state {
+                State::Start => match byte {
+                    b'{' => self.state = State::Key,
+                    _ if byte.is_ascii_whitespace() => {}
+                    _ => bail!("Expected a JSON object"),
+                },
+                State::Key => match byte {
+                    b'"' => {
+                        self.key.clear();
+                        self.escaped = false;
+                        self.state = State::KeyString;
+                    }
+                    b'}' => self.state = State::Done,
+                    _ if byte.is_ascii_whitespace() => {}
+                    _ => bail!("Expected a key"),
+                },
+                // Keys are short, so escapes in them are kept as is
+                State::KeyString => match byte {
+                    b'"' if !self.escaped => self.state = State::Colon,
+                    _ => {
+                        self.escaped = byte == b'\\' && !self.escaped;
+                        self.key.push(byte);
+                    }
+                },
+                State::Colon => match byte {
+                    b':' => self.state = State::Value,
+                    _ if byte.is_ascii_whitespace() => {}
+                    _ => bail!("Expected a colon"),
+                },
+                State::Value => match byte {
+                    b'"' => self.state = State::StringValue,
+                    _ if byte.is_ascii_whitespace() => {}
+                    _ => {
+                        self.state = State::Skip;
+                        self.depth = 0;
+                        self.in_string = false;
+                        self.escaped = false;
+                        self.skip(byte);
+                    }
+                },
+                State::StringValue => {
+                    if !self.escape.is_empty() {
+                        self.escape.push(byte);
+                        self.unescape(&mut value)?;
+                    } else if byte == b'\\' {
+                        self.escape.push(byte);
+                    } else if byte == b'"' {
+                        self.unpaired(&mut value);
+                        self.flush(&mut pieces, &mut value);
+                        self.state = State::Next;
+                    } else {
+                        self.unpaired(&mut value);
+                        value.push(byte);
+                    }
+                }
+                State::Skip => self.skip(byte),
+                State::Next => match byte {
+                    b',' => self.state = State::Key,
+                    b'}' => self.state = State::Done,
+                    _ if byte.is_ascii_whitespace() => {}
+                    _ => bail!("Expected a comma"),
+                },
+                State::Done => {
+                    if !byte.is_ascii_whitespace() {
+                        bail!("Trailing data after the JSON object");
+                    }
+                }
+            }
+        }
+        self.flush(&mut pieces, &mut value);
+        Ok(pieces)
+    }
+
+    /// Whether the whole object has been read.
+    pub fn finished(&self) -> bool {
+        self.state == State::Done
+    }
+
+    fn flush(&self, pieces: &mut Vec<(String, Vec<u8>)>, value: &mut Vec<u8>) {
+        if !value.is_empty() {
+            let key = String::from_utf8_lossy(&self.key).into_owned();
+            pieces.push((key, std::mem::take(value)));
+        }
+    }
+
+    // Values end at a comma or brace outside of any nesting or string
+    fn skip(&mut self, byte: u8) {
+        if self.in_string {
+            match byte {
+                _ if self.escaped => self.escaped = false,
+                b'\\' => self.escaped = true,
+                b'"' => self.in_string = false,
+                _ => {}
+            }
+            return;
+        }
+        match byte {
+            b'"' => self.in_string = true,
+            b'{' | b'[' => self.depth += 1,
+            b'}' | b']' if self.depth > 0 => self.depth -= 1,
+            b'}' if self.depth == 0 => self.state = State::Done,
+            b',' if self.depth == 0 => self.state = State::Key,
+            _ => {}
+        }
+    }
+
+    // Decodes the pending escape once it is complete
+    fn unescape(&mut self, value: &mut Vec<u8>) -> Result<()> {
+        let simple = match self.escape[1] {
+            b'"' => Some(b'"'),
+            b'\\' => Some(b'\\'),
+            b'/' => Some(b'/'),
+            b'b' => Some(0x08),
+            b'f' => Some(0x0c),
+            b'n' => Some(b'\n'),
+            b'r' => Some(b'\r'),
+            b't' => Some(b'\t'),
+            b'u' => None,
+            other => bail!("Invalid escape \\{}", other as char),
+        };
+        if let Some(byte) = simple {
+            self.unpaired(value);
+            value.push(byte);
+            self.escape.clear();
+            return Ok(());
+        }
+        if self.escape.len() < 6 {
+            return Ok(());
+        }
+
+        let hex = std::str::from_utf8(&self.escape[2..6])?;
+        let unit = u16::from_str_radix(hex, 16)?;
+        self.escape.clear();
+        let code = match (self.surrogate, unit) {
+            (Some(high), 0xdc00..=0xdfff) => {
+                self.surrogate = None;
+                0x10000 + ((u32::from(high) - 0xd800) << 10) + (u32::from(unit) - 0xdc00)
+            }
+            (_, 0xd800..=0xdbff) => {
+                self.unpaired(value);
+                self.surrogate = Some(unit);
+                return Ok(());
+            }
+            (_, unit) => {
+                self.unpaired(value);
+                u32::from(unit)
+            }
+        };
+        // Lone low surrogates aren't characters either
+        push_char(
+            value,
+            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER),
+        );
+        Ok(())
+    }
+
+    // A high surrogate that isn't followed by a low one is replaced like invalid UTF-8 is
+    fn unpaired(&mut self, value: &mut Vec<u8>) {
+        if self.surrogate.take().is_some() {
+            push_char(value, char::REPLACEMENT_CHARACTER);
+        }
+    }
+}
+
+fn push_char(value: &mut Vec<u8>, character: char) {
+    let mut buffer = [0; 4];
+    value.extend_from_slice(character.encode_utf8(&mut buffer).as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    // Decodes the chunks in order, joining each field's pieces
+    fn decode(chunks: &[&[u8]]) -> HashMap<String, String> {
+        let mut fields = StringFields::new();
+        let mut values: HashMap<String, Vec<u8>> = HashMap::new();
+        for chunk in chunks {
+            for (key, bytes) in fields.feed(chunk).unwrap() {
+                values.entry(key).or_default().extend(bytes);
+            }
+        }
+        assert!(fields.finished());
+        values
+            .into_iter()
+            .map(|(key, bytes)| (key, String::from_utf8(bytes).unwrap()))
+            .collect()
+    }
+
+    // Splitting the input at any point, or feeding it a byte at a time, decodes the same
+    fn assert_decodes(input: &str, expected: &[(&str, &str)]) {
+        let expected: HashMap<String, String> = expected
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let bytes = input.as_bytes();
+        for split in 0..=bytes.len() {
+            let (head, tail) = bytes.split_at(split);
+            assert_eq!(decode(&[head, tail]), expected, "split at {}", split);
+        }
+        let bytewise: Vec<&[u8]> = bytes.chunks(1).collect();
+        assert_eq!(decode(&bytewise), expected, "byte by byte");
+    }
+
+    #[test]
+    fn escapes() {
+        assert_decodes(
+            r#"{"problem_input": "a\"b\\c\/d\b\f\n\r\té€", "problem_output": "5\n"}"#,
+            &[
+                ("problem_input", "a\"b\\c/d\u{8}\u{c}\n\r\t\u{e9}\u{20ac}"),
+                ("problem_output", "5\n"),
+            ],
+        );
+    }
+
+    #[test]
+    fn raw_utf8() {
+        assert_decodes(
+            r#"{"problem_input": "héllo é€😀"}"#,
+            &[("problem_input", "héllo é€😀")],
+        );
+    }
+
+    #[test]
+    fn skipped_values() {
+        assert_decodes(
+            r#"{"id": 12, "nested": {"a": ["}", "\"", {"b": null}]}, "ok": true, "name": "x"}"#,
+            &[("name", "x")],
+        );
+    }
+
+    #[test]
+    fn surrogate_pairs() {
+        assert_decodes(
+            r#"{"problem_input": "a\ud83d\ude00b\ud83d\ude01"}"#,
+            &[("problem_input", "a\u{1f600}b\u{1f601}")],
+        );
+    }
+
+    #[test]
+    fn unpaired_surrogates() {
+        assert_decodes(
+            r#"{"a": "\ud83dA", "b": "\ud83dB", "c": "\ud83d\n", "d": "\ud83d\ud83d\ude00", "e": "\ud83d", "f": "\ude00x"}"#,
+            &[
+                ("a", "\u{fffd}A"),
+                ("b", "\u{fffd}B"),
+                ("c", "\u{fffd}\n"),
+                ("d", "\u{fffd}\u{1f600}"),
+                ("e", "\u{fffd}"),
+                ("f", "\u{fffd}x"),
+            ],
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(StringFields::new().feed(b"[1]").is_err());
+        assert!(StringFields::new().feed(br#"{"a": "\x"}"#).is_err());
+        assert!(StringFields::new().feed(br#"{"a": "b"} c"#).is_err());
+    }
+}
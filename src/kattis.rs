@@ -0,0 +1,171 @@
+use crate::problem::{
+    self, Checker, CheckerKind, Limits, LocalProblem, TestCase, Validator, ValidatorKind,
+};
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize, Debug, Default)]
+struct ProblemYaml {
+    // `default` or `custom`, optionally followed by `interactive` or `score`
+    #[serde(default)]
+    validation: Option<String>,
+    #[serde(default)]
+    limits: YamlLimits,
+    // Arguments for the output validator, separated by whitespace
+    #[serde(default)]
+    validator_flags: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct YamlLimits {
+    // Seconds, only in newer versions of the format
+    #[serde(default)]
+    time_limit: Option<f64>,
+    // MiB
+    #[serde(default)]
+    memory: Option<u64>,
+}
+
+/// Reads a Kattis/ICPC problem package: `problem.yaml`, tests under
+/// `data/sample` and `data/secret`, and optionally `submissions/`,
+/// `input_validators/` and `output_validators/`.
+pub fn load(dir: &Path) -> Result<LocalProblem> {
+    let yaml_path = dir.join("problem.yaml");
+    let yaml = fs::read_to_string(&yaml_path)
+        .with_context(|| format!("Unable to read {}", yaml_path.display()))?;
+    // An empty problem.yaml is valid and means every default
+    let yaml: ProblemYaml = serde_yaml::from_str::<Option<ProblemYaml>>(&yaml)
+        .with_context(|| format!("Unable to parse {}", yaml_path.display()))?
+        .unwrap_or_default();
+
+    let validation = yaml.validation.unwrap_or(String::from("default"));
+    if validation.contains("interactive") {
+        bail!("Interactive problems can't be judged");
+    }
+
+    // Older packages leave the time limit to the tooling, which writes it to .timelimit
+    let time_limit = match yaml.limits.time_limit {
+        Some(time_limit) => time_limit,
+        None => fs::read_to_string(dir.join(".timelimit"))
+            .context("problem.yaml has no limits.time_limit and there is no .timelimit file")?
+            .trim()
+            .parse()
+            .context("Unable to parse .timelimit")?,
+    };
+
+    let data = dir.join("data");
+    let mut tests = Vec::new();
+    for group in ["sample", "secret"] {
+        walk_tests(&data.join(group), &data, &mut tests)?;
+    }
+    if tests.is_empty() {
+        bail!("No tests found in {}", data.display());
+    }
+
+    let flags: Vec<String> = yaml
+        .validator_flags
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(String::from)
+        .collect();
+    let checker = if validation.starts_with("custom") {
+        Some(output_validator(&dir.join("output_validators"), flags)?)
+    } else if !flags.is_empty() {
+        // The default validator's flags loosen the comparison, judging exactly would be wrong
+        bail!(
+            "validator_flags are only supported with validation: custom, outputs are compared exactly"
+        );
+    } else {
+        None
+    };
+
+    Ok(LocalProblem {
+        limits: Limits {
+            time_limit: (time_limit * 1000.0).round() as u64,
+            memory: yaml.limits.memory,
+        },
+        tests,
+        solutions: problem::solutions(dir)?,
+        validators: programs(&dir.join("input_validators"))?
+            .into_iter()
+            .map(|path| Validator {
+                path,
+                kind: ValidatorKind::Kattis,
+            })
+            .collect(),
+        checker,
+        includes: Vec::new(),
+    })
+}
+
+// Test groups can be nested, each level is taken in name order
+fn walk_tests(dir: &Path, root: &Path, tests: &mut Vec<TestCase>) -> Result<()> {
+    if !dir.exists() {
+        return Ok(());
+    }
+    tests.extend(problem::test_cases(dir, root)?);
+    let mut groups = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Unable to read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.is_dir() {
+            groups.push(path);
+        }
+    }
+    groups.sort();
+    for group in groups {
+        walk_tests(&group, root, tests)?;
+    }
+    Ok(())
+}
+
+// Validators are either single files or dirs holding one program's sources
+fn programs(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut programs = problem::files(dir)?;
+    if dir.exists() {
+        let mut dirs = Vec::new();
+        for entry in
+            fs::read_dir(dir).with_context(|| format!("Unable to read {}", dir.display()))?
+        {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            }
+        }
+        dirs.sort();
+        for dir in dirs {
+            programs.push(main_source(&dir)?);
+        }
+    }
+    Ok(programs)
+}
+
+// The source to build from a program dir, the only source file in it
+fn main_source(dir: &Path) -> Result<PathBuf> {
+    let sources: Vec<PathBuf> = problem::files(dir)?
+        .into_iter()
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ["cpp", "cc", "cxx", "py"].iter().any(|known| ext == *known))
+        })
+        .collect();
+    match sources.as_slice() {
+        [source] => Ok(source.clone()),
+        [] => bail!("No source file found in {}", dir.display()),
+        _ => bail!("More than one source file in {}", dir.display()),
+    }
+}
+
+fn output_validator(dir: &Path, flags: Vec<String>) -> Result<Checker> {
+    match programs(dir)?.as_slice() {
+        [path] => Ok(Checker {
+            path: path.clone(),
+            kind: CheckerKind::Kattis,
+            flags,
+        }),
+        [] => bail!("Problem uses custom validation but has no output validator"),
+        _ => bail!("More than one output validator in {}", dir.display()),
+    }
+}
@@ -0,0 +1,458 @@
+use crate::config::Config;
+use crate::docker::{container_state, kill_container, rm_container};
+use crate::problem::{Checker, CheckerKind, LocalProblem, TestCase};
+use crate::program::Program;
+use crate::resources::{self, ResourcePool};
+use crate::runner::{job_dir, read_run, run_submission, JobResult};
+use crate::source::{FinishedSubmissions, Problem, Progress, Submission, SubmissionSource};
+use crate::Languages;
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use clap::Args;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{env, fs, process};
+
+// Largest number of differing lines shown for a wrong answer
+const DIFF_LINES: usize = 10;
+
+#[derive(Args, Debug)]
+pub struct JudgeArgs {
+    /// Problem directory, a Kattis/ICPC or Polygon package or the layout in the README
+    pub problem: PathBuf,
+    /// Solution to judge
+    pub source: PathBuf,
+    /// Language of the solution, guessed from its extension when left out
+    #[arg(short, long, value_enum)]
+    pub language: Option<Languages>,
+    /// Memory in MB when the problem doesn't set a limit
+    #[arg(long, default_value_t = 1024)]
+    pub memory: u64,
+}
+
+// Serves a local problem's tests to the runner, test `i` is problem `i`
+struct LocalSource {
+    tests: Vec<Problem>,
+}
+
+#[async_trait]
+impl SubmissionSource for LocalSource {
+    async fn pending(&self, _judge: &str, _count: usize) -> Result<Vec<Submission>> {
+        Ok(Vec::new())
+    }
+
+    async fn problem(&self, problem_id: i32) -> Result<Problem> {
+        let test = usize::try_from(problem_id)
+            .ok()
+            .and_then(|index| self.tests.get(index))
+            .with_context(|| format!("No test {}", problem_id))?;
+        Ok(Problem {
+            problem_input: test.problem_input.clone(),
+            problem_output: test.problem_output.clone(),
+        })
+    }
+
+    async fn report(&self, results: Vec<FinishedSubmissions>) -> Result<Vec<i32>> {
+        Ok(results.into_iter().map(|result| result.id).collect())
+    }
+
+    async fn progress(&self, _judge: &str, _id: i32, _progress: Progress) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub struct Outcome {
+    pub result: Option<JobResult>,
+    // How long the solution ran, as the judge image measured it
+    pub time: Option<Duration>,
+    // What the solution printed, or the compiler errors
+    pub output: String,
+    // Whether the kernel killed the solution for going over the memory limit
+    pub oom_killed: bool,
+}
+
+/// The work dir for local judging, with its own problems/ and jobs/ so it can't
+/// clash with a judge serving from this machine. Removed when this is dropped.
+pub struct ScratchDir {
+    path: PathBuf,
+}
+
+impl ScratchDir {
+    pub fn create() -> Result<Self> {
+        let path = env::temp_dir().join(format!("debussy-judge-{}", process::id()));
+        for dir in ["problems", "jobs", "bin"] {
+            fs::create_dir_all(path.join(dir))
+                .with_context(|| format!("Unable to create {}", path.join(dir).display()))?;
+        }
+        Ok(ScratchDir { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Where validators and checkers are built
+    pub fn bin(&self) -> PathBuf {
+        self.path.join("bin")
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Runs solutions against a local problem's tests in the same sandbox the server
+/// uses, working in a `ScratchDir`.
+pub struct LocalJudge {
+    source: LocalSource,
+    tests: Vec<TestCase>,
+    checker: Option<(Program, Checker)>,
+    work_dir: PathBuf,
+    bin: PathBuf,
+    // Input and output checksums of each test
+    sums: Vec<(String, String)>,
+    pool: Arc<ResourcePool>,
+    // Milliseconds per test
+    time_limit: u64,
+    memory: u64,
+    next_id: AtomicI32,
+    config: Config,
+}
+
+impl LocalJudge {
+    pub async fn new(
+        problem: &LocalProblem,
+        default_memory: u64,
+        scratch: &ScratchDir,
+        config: &Config,
+    ) -> Result<Self> {
+        let mut tests = Vec::new();
+        for test in &problem.tests {
+            tests.push(Problem {
+                problem_input: fs::read_to_string(&test.input)
+                    .with_context(|| format!("Unable to read {}", test.input.display()))?,
+                problem_output: fs::read_to_string(&test.output)
+                    .with_context(|| format!("Unable to read {}", test.output.display()))?,
+            });
+        }
+        let sums = tests
+            .iter()
+            .map(|test| {
+                (
+                    format!("{:x}", md5::compute(&test.problem_input)),
+                    format!("{:x}", md5::compute(&test.problem_output)),
+                )
+            })
+            .collect();
+
+        let checker = match &problem.checker {
+            Some(checker) => Some((
+                Program::build(&checker.path, &problem.includes, &scratch.bin()).await?,
+                checker.clone(),
+            )),
+            None => None,
+        };
+
+        let memory = problem.limits.memory.unwrap_or(default_memory);
+        Ok(LocalJudge {
+            source: LocalSource { tests },
+            tests: problem.tests.clone(),
+            checker,
+            work_dir: scratch.path().to_path_buf(),
+            bin: scratch.bin(),
+            sums,
+            pool: ResourcePool::new(
+                resources::host_cores(),
+                resources::host_memory().unwrap_or(memory).max(memory),
+                false,
+            ),
+            time_limit: problem.limits.time_limit,
+            memory,
+            // Negative ids keep container names apart from the submissions a server judges
+            next_id: AtomicI32::new(-((process::id() % 1_000_000) as i32) * 1000),
+            config: config.clone(),
+        })
+    }
+
+    pub fn time_limit(&self) -> Duration {
+        Duration::from_millis(self.time_limit)
+    }
+
+    pub fn expected_output(&self, test: usize) -> &str {
+        &self.source.tests[test].problem_output
+    }
+
+    /// Runs `content` on a single test.
+    pub async fn run(&self, language: Languages, content: &str, test: usize) -> Result<Outcome> {
+        let (input_sum, output_sum) = self.sums[test].clone();
+        let id = self.next_id.fetch_sub(1, Ordering::Relaxed) - 1;
+        let submission = Submission {
+            id,
+            language,
+            content: content.to_string(),
+            problem: test as i32,
+            input_sum,
+            output_sum,
+            timeout: self.time_limit.div_ceil(1000) as i32,
+            timeout_ms: Some(self.time_limit),
+            memory_limit: Some(self.memory),
+            judging: None,
+        };
+        let reservation = self.pool.reserve(1, self.memory).await?;
+        let dir = job_dir(&self.work_dir, id);
+        let container = run_submission(
+            &self.work_dir,
+            submission,
+            &self.source,
+            &reservation,
+            "local",
+            &self.config,
+        )
+        .await?;
+
+        // The image enforces the time limit itself, this only catches a stuck container
+        let deadline = Instant::now() + self.time_limit() + Duration::from_secs(60);
+        let state = loop {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let state = container_state(container.clone(), self.config.docker.url.clone()).await?;
+            if !state.running {
+                break state;
+            }
+            if Instant::now() > deadline {
+                let _ = kill_container(container.clone(), self.config.docker.url.clone()).await;
+                let _ = rm_container(container.clone(), self.config.docker.url.clone()).await;
+                let _ = tokio::fs::remove_dir_all(&dir).await;
+                return Err(anyhow!("Test container {} never finished", container));
+            }
+        };
+        let _ = rm_container(container, self.config.docker.url.clone()).await;
+
+        let run = read_run(&dir).await;
+        let mut result = JobResult::from_i32(state.exit_code);
+        // The image only compares outputs exactly, a checker has the final say
+        let checked = match &self.checker {
+            Some((program, checker))
+                if matches!(result, Some(JobResult::Correct) | Some(JobResult::Wrong)) =>
+            {
+                Some(
+                    self.check(program, checker, test, id, &dir.join("stdout.txt"))
+                        .await,
+                )
+            }
+            _ => None,
+        };
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        if let Some(checked) = checked {
+            result = Some(checked?);
+        }
+
+        let output = if result == Some(JobResult::CompilerError) {
+            run.compiler
+        } else {
+            run.stdout
+        };
+        Ok(Outcome {
+            result,
+            time: run.time.map(Duration::from_millis),
+            output: output.unwrap_or_default(),
+            oom_killed: state.oom_killed,
+        })
+    }
+
+    async fn check(
+        &self,
+        program: &Program,
+        checker: &Checker,
+        test: usize,
+        id: i32,
+        output_path: &Path,
+    ) -> Result<JobResult> {
+        let test = &self.tests[test];
+        if !output_path.exists() {
+            bail!(
+                "The judge image didn't keep the solution's output in stdout.txt for the checker"
+            );
+        }
+
+        let kind = checker.kind;
+        let mut command = program.command();
+        let status = match kind {
+            CheckerKind::Kattis => {
+                let feedback = self.bin.join(format!("feedback-{}", -id));
+                fs::create_dir_all(&feedback)?;
+                let status = command
+                    .arg(&test.input)
+                    .arg(&test.output)
+                    .arg(&feedback)
+                    .args(&checker.flags)
+                    .stdin(Stdio::from(fs::File::open(output_path)?))
+                    .stdout(Stdio::null())
+                    .status()
+                    .await;
+                let _ = fs::remove_dir_all(&feedback);
+                status
+            }
+            CheckerKind::Testlib => {
+                command
+                    .arg(&test.input)
+                    .arg(output_path)
+                    .arg(&test.output)
+                    .args(&checker.flags)
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status()
+                    .await
+            }
+        }
+        .context("Unable to run checker")?;
+
+        match (kind, status.code()) {
+            (CheckerKind::Kattis, Some(42)) | (CheckerKind::Testlib, Some(0)) => {
+                Ok(JobResult::Correct)
+            }
+            (CheckerKind::Kattis, Some(43)) | (CheckerKind::Testlib, Some(1 | 2)) => {
+                Ok(JobResult::Wrong)
+            }
+            (_, code) => bail!(
+                "Checker failed on test {} with exit code {:?}",
+                test.name,
+                code
+            ),
+        }
+    }
+}
+
+pub fn guess_language(source: &Path) -> Option<Languages> {
+    match source.extension()?.to_str()? {
+        "py" => Some(Languages::Python),
+        "java" => Some(Languages::Java),
+        "cpp" | "cc" | "cxx" => Some(Languages::Cpp),
+        _ => None,
+    }
+}
+
+pub fn format_verdict(result: Option<JobResult>) -> String {
+    result
+        .map(|result| format!("{:?}", result))
+        .unwrap_or(String::from("Unknown"))
+}
+
+pub fn format_time(time: Option<Duration>) -> String {
+    time.map(|time| format!("{:.2}s", time.as_secs_f64()))
+        .unwrap_or(String::from("-"))
+}
+
+// A test's verdict as printed by `judge --json`
+#[derive(Serialize, Debug)]
+struct Judged {
+    test: String,
+    verdict: String,
+    // Seconds
+    time: Option<f64>,
+}
+
+/// Judges a source file against every test of a local problem, printing each
+/// verdict. Returns whether every test was correct.
+pub async fn judge(args: JudgeArgs, config: &Config, json: bool) -> Result<bool> {
+    let problem = LocalProblem::load(&args.problem)?;
+    let language = match args.language {
+        Some(language) => language,
+        None => guess_language(&args.source).with_context(|| {
+            format!(
+                "Unable to tell the language of {}, pass --language",
+                args.source.display()
+            )
+        })?,
+    };
+    let content = fs::read_to_string(&args.source)
+        .with_context(|| format!("Unable to read {}", args.source.display()))?;
+
+    let scratch = ScratchDir::create()?;
+    let local = LocalJudge::new(&problem, args.memory, &scratch, config).await?;
+
+    let count = problem.tests.len();
+    let mut passed = 0;
+    let mut slowest = Duration::ZERO;
+    let mut judged = Vec::new();
+    for (index, test) in problem.tests.iter().enumerate() {
+        let outcome = local.run(language, &content, index).await?;
+        slowest = slowest.max(outcome.time.unwrap_or_default());
+        judged.push(Judged {
+            test: test.name.clone(),
+            verdict: format_verdict(outcome.result),
+            time: outcome.time.map(|time| time.as_secs_f64()),
+        });
+        if outcome.result == Some(JobResult::Correct) {
+            passed += 1;
+        }
+        if !json {
+            println!(
+                "test {}/{} {:<20} {:<20} {}",
+                index + 1,
+                count,
+                test.name,
+                format_verdict(outcome.result),
+                format_time(outcome.time)
+            );
+            if outcome.result == Some(JobResult::Wrong) {
+                print_diff(local.expected_output(index), &outcome.output);
+            }
+        }
+        // Every other test would fail to compile the same way
+        if outcome.result == Some(JobResult::CompilerError) {
+            if !json {
+                println!("{}", outcome.output.trim_end());
+            }
+            break;
+        }
+    }
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({ "passed": passed, "tests": count, "results": judged })
+        );
+    } else {
+        println!(
+            "{}/{} tests correct, slowest {:.2}s",
+            passed,
+            count,
+            slowest.as_secs_f64()
+        );
+    }
+    Ok(passed == count)
+}
+
+// Shows the lines that differ, ignoring trailing whitespace
+fn print_diff(expected: &str, got: &str) {
+    let expected: Vec<&str> = expected.lines().map(str::trim_end).collect();
+    let got: Vec<&str> = got.lines().map(str::trim_end).collect();
+    let mut shown = 0;
+    for line in 0..expected.len().max(got.len()) {
+        let want = expected.get(line);
+        let have = got.get(line);
+        if want == have {
+            continue;
+        }
+        if shown == DIFF_LINES {
+            println!("  ...");
+            break;
+        }
+        shown += 1;
+        println!("  line {}:", line + 1);
+        println!(
+            "    expected {}",
+            want.map_or(String::from("<missing>"), |l| format!("{:?}", l))
+        );
+        println!(
+            "    got      {}",
+            have.map_or(String::from("<missing>"), |l| format!("{:?}", l))
+        );
+    }
+}
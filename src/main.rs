@@ -1,272 +1,320 @@
+mod archive;
 mod cache;
+mod cache_command;
+mod config;
+mod containers;
 mod docker;
+mod doctor;
+mod domjudge;
 mod error;
+mod intake;
+mod janitor;
+mod jsonstream;
+mod kattis;
+mod local;
+mod outbox;
+mod polygon;
+mod prefetch;
+mod problem;
+mod program;
 mod ravel;
+mod reload;
+mod resources;
 mod runner;
-use crate::docker::{container_state, kill_container, rm_container};
-use crate::runner::JobResult::Correct;
-use crate::runner::{run_submission, JobResult, JobStatus};
-use chrono::{NaiveTime, Utc};
-use dotenvy;
+mod scheduler;
+mod signals;
+mod source;
+mod state;
+mod suite;
+mod validate;
+use crate::config::{Config, ConfigAction, ConfigArgs, ConfigCommandArgs, IntakeMode};
+use crate::intake::Intake;
+use crate::outbox::Outbox;
+use crate::prefetch::Readiness;
+use crate::resources::ResourcePool;
+use crate::scheduler::{Judge, Mode};
+use crate::state::StateStore;
+use anyhow::{bail, Context};
+use clap::{Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fs;
-use std::path::Path;
-use tracing;
-use tracing::{error, info, span};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tracing::{error, info, Level};
 
-#[derive(Deserialize, Serialize, Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Eq, PartialEq, Hash, ValueEnum)]
 pub enum Languages {
     Python,
     Java,
     Cpp,
 }
 
-struct Job {
-    submission: ravel::Submission,
-    span: tracing::Span,
-    status: JobStatus,
-    started: Option<NaiveTime>,
+#[derive(Parser, Debug)]
+#[command(version, about = "Judge for Ravel")]
+struct Cli {
+    /// Configuration file, debussy.toml in the working directory by default
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Most verbose logs shown, info for serve and warn otherwise by default
+    #[arg(long, global = true)]
+    log_level: Option<Level>,
+    /// Print results, and logs, as JSON
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Judge submissions from Ravel (the default)
+    Serve(ConfigArgs),
+    /// Judge a solution against a local problem without a server
+    Judge(local::JudgeArgs),
+    /// Check a problem package against its reference solutions and validators
+    ValidateProblem(validate::ValidateArgs),
+    /// Inspect, verify and purge the problem cache
+    Cache(cache_command::CacheArgs),
+    /// List and clean up judge containers
+    Containers(containers::ContainersArgs),
+    /// Check docker, the judge image, the source and the sandbox's verdicts
+    Doctor(doctor::DoctorArgs),
+    /// Check the configuration
+    Config(ConfigCommandArgs),
 }
 
 #[tokio::main]
 async fn main() {
-    // Init env vars
-    dotenvy::dotenv().expect("Dotenvy not initialized");
-    let url = dotenvy::var("ravel_url").expect("No ravel_url set in .env");
-    let max_jobs = dotenvy::var("max_jobs")
-        .expect("No max_jobs sent in .env")
-        .parse()
-        .expect("max_jobs should be and int");
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Serve(ConfigArgs::default()));
+    let default_level = match command {
+        Command::Serve(_) => Level::INFO,
+        _ => Level::WARN,
+    };
+    init_logging(cli.log_level.unwrap_or(default_level), cli.json);
 
-    let mut ravel_creds = HashMap::new();
-    ravel_creds.insert(
-        "username",
-        dotenvy::var("ravel_username").expect("No username var"),
-    );
-    ravel_creds.insert(
-        "password",
-        dotenvy::var("ravel_password").expect("No username var"),
-    );
+    let args = ConfigArgs {
+        config: cli.config,
+        ..ConfigArgs::default()
+    };
+    match command {
+        Command::Serve(serve_args) => {
+            let serve_args = ConfigArgs {
+                config: args.config,
+                ..serve_args
+            };
+            if let Err(err) = serve(serve_args).await {
+                eprintln!("{:#}", err);
+                std::process::exit(2);
+            }
+        }
+        Command::Config(command) => match command.action {
+            ConfigAction::Check(check_args) => exit_with(config::check(
+                ConfigArgs {
+                    config: args.config,
+                    ..check_args
+                },
+                cli.json,
+            )),
+        },
+        command => exit_with(run(command, &args, cli.json).await),
+    }
+}
 
-    let subscriber = tracing_subscriber::fmt()
-        .pretty()
-        .with_file(true)
-        .with_line_number(true)
-        .with_thread_ids(false)
-        .with_target(false)
-        .finish();
-    tracing::subscriber::set_global_default(subscriber)
-        .expect("Unable to set subscribe as default");
+// Logs go to stderr so they don't mix with the output of the other commands
+fn init_logging(level: Level, json: bool) {
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
+        .with_target(false);
+    let result = if json {
+        tracing::subscriber::set_global_default(builder.json().finish())
+    } else {
+        tracing::subscriber::set_global_default(
+            builder
+                .pretty()
+                .with_file(true)
+                .with_line_number(true)
+                .with_thread_ids(false)
+                .finish(),
+        )
+    };
+    result.expect("Unable to set subscribe as default");
+}
 
-    // Init problem dir
-    if !Path::exists(Path::new("problems/")) {
-        fs::create_dir("problems/").expect("Unable to create problems directory");
+// The one-off commands, which use the configured docker host, image and work dir
+async fn run(command: Command, args: &ConfigArgs, json: bool) -> anyhow::Result<bool> {
+    let config = Config::load(args)?;
+    match command {
+        Command::Judge(args) => local::judge(args, &config, json).await,
+        Command::ValidateProblem(args) => validate::validate(args, &config, json).await,
+        Command::Cache(args) => cache_command::cache(args, &config, json).await,
+        Command::Containers(args) => containers::containers(args, &config, json).await,
+        Command::Doctor(args) => doctor::doctor(args, &config, json).await,
+        Command::Serve(_) | Command::Config(_) => unreachable!("handled in main"),
     }
+}
 
-    // Init jobs dir
-    if Path::exists(Path::new("jobs/")) {
-        fs::remove_dir_all("jobs/").expect("Unable to clear jobs directory");
+// Exits with 0 when a check passed, 1 when it failed and 2 when it couldn't run
+fn exit_with(passed: anyhow::Result<bool>) {
+    match passed {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(err) => {
+            eprintln!("{:#}", err);
+            std::process::exit(2);
+        }
     }
-    fs::create_dir("jobs/").expect("Unable to create jobs directory");
+}
 
-    let client = reqwest::Client::builder().build().unwrap();
+async fn serve(mut args: ConfigArgs) -> anyhow::Result<()> {
+    let mut config = Config::load(&args)?;
+    config.validate()?;
+    // Reloads happen from inside the work dir, so they need absolute paths
+    let config_path = Config::locate(&args).map(|path| std::path::absolute(&path).unwrap_or(path));
+    config.paths.work_dir = std::path::absolute(&config.paths.work_dir)?;
+    args.config = config_path.clone();
+    args.work_dir = Some(config.paths.work_dir.clone());
+    let max_jobs = config.max_jobs.unwrap_or(1);
 
-    let mut jobs = HashMap::new();
-    let mut timestamp = Utc::now().time();
-    let mut num_running_jobs = 0;
-    let mut finished = ravel::Update {
-        username: ravel_creds.get("username").unwrap().to_owned(),
-        password: ravel_creds.get("password").unwrap().to_owned(),
-        submissions: Vec::new(),
-    };
+    let judge_id = config.judge_id();
 
-    loop {
-        // Process submissions from Ravel
-        if (Utc::now().time() - timestamp).num_seconds() >= 1 {
-            timestamp = Utc::now().time();
-            match ravel::get_submissions(&ravel_creds, &client, &url).await {
-                Ok(subs) => {
-                    for sub in subs {
-                        if !jobs.contains_key(&sub.id) {
-                            jobs.insert(
-                                sub.id,
-                                Job {
-                                    span: span!(tracing::Level::TRACE, "Submission", id = sub.id,),
-                                    submission: sub,
-                                    status: JobStatus::Pending,
-                                    started: None,
-                                },
-                            );
-                        }
-                    }
-                }
-                Err(err) => {
-                    error!("Unable to fetch submissions with error: {}", err);
-                }
-            }
-        }
+    // Resources shared by all jobs, defaulting to the whole host
+    let reserved_cores = config.reserved_cores()?;
+    let host_cores = resources::host_cores();
+    if let Some(core) = reserved_cores
+        .iter()
+        .find(|core| !host_cores.contains(core))
+    {
+        bail!("reserved_cores contains core {} which is not online", core);
+    }
+    let mut judging_cores: Vec<usize> = host_cores
+        .iter()
+        .copied()
+        .filter(|core| !reserved_cores.contains(core))
+        .collect();
+    // Hyperthreads of reserved cores share their caches with the judge, so they
+    // are the last ones given to submissions
+    let shared: Vec<usize> = reserved_cores
+        .iter()
+        .flat_map(|core| resources::siblings(*core))
+        .collect();
+    judging_cores.sort_by_key(|core| shared.contains(core));
+    if let Some(cores) = config.resources.total_cores {
+        judging_cores.truncate(cores);
+    }
+    if judging_cores.len() < config.resources.job_cores {
+        bail!(
+            "Only {} cores are left for judging after reserved_cores but job_cores is {}",
+            judging_cores.len(),
+            config.resources.job_cores
+        );
+    }
+    let total_memory = match config.resources.total_memory {
+        Some(memory) => memory,
+        None => resources::host_memory().context("Unable to detect memory, set total_memory")?,
+    };
 
-        for job in jobs.values_mut() {
-            let _enter = job.span.enter();
-            match job.status {
-                JobStatus::Pending => {
-                    if num_running_jobs <= max_jobs {
-                        info!("Running submission '{}'", job.submission.id);
-                        match run_submission(job.submission.clone(), &client, &ravel_creds, &url)
-                            .await
-                        {
-                            Ok(_) => {
-                                num_running_jobs += 1;
-                                job.status = JobStatus::Running;
-                                job.started = Some(Utc::now().time());
-                                info!("Judging submission '{}', has started", job.submission.id)
-                            }
-                            Err(err) => {
-                                error!(
-                                    "Encountered an error running submission '{}': '{}'",
-                                    job.submission.id, err
-                                );
-                            }
-                        }
-                    }
-                }
-                JobStatus::Running => {
-                    if let Ok(state) = container_state(
-                        format!("reverie_{}", job.submission.id),
-                        String::from("http://localhost:2375"),
-                    )
-                    .await
-                    {
-                        if state.running == false {
-                            job.status = JobStatus::Finished;
-                            num_running_jobs -= 1;
-                        } else if job.started.is_some() {
-                            if (Utc::now().time() - job.started.unwrap()).num_minutes() >= 10 {
-                                match kill_container(
-                                    format!("reverie_{}", job.submission.id),
-                                    String::from("http://localhost:2375"),
-                                )
-                                .await
-                                {
-                                    Ok(_) => {}
-                                    Err(err) => {
-                                        error!(
-                                            "Unable to kill job {} with error: {}",
-                                            job.submission.id, err
-                                        )
-                                    }
-                                }
+    if !reserved_cores.is_empty() {
+        resources::pin_judge(&reserved_cores).context("Unable to pin judge to reserved_cores")?;
+    }
 
-                                match tokio::fs::write(
-                                    format!("problems/{}/status.txt", job.submission.id),
-                                    "Timelimit Exception",
-                                )
-                                .await
-                                {
-                                    Ok(_) => {}
-                                    Err(err) => {
-                                        error!(
-                                    "Unable to write timeout for job '{}' with the following error: '{}'",
-                                    job.submission.id, err);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-                JobStatus::Finished => {
-                    info!("Submission '{}' has finished running", job.submission.id);
+    // problems/, jobs/ and the state dir are all relative to the work dir
+    std::env::set_current_dir(&config.paths.work_dir).with_context(|| {
+        format!(
+            "Unable to enter work_dir {}",
+            config.paths.work_dir.display()
+        )
+    })?;
+    fs::create_dir_all("problems/").context("Unable to create problems directory")?;
+    // Leftover job dirs are cleaned up by the janitor
+    fs::create_dir_all("jobs/").context("Unable to create jobs directory")?;
 
-                    let exit_code = match container_state(
-                        format!("reverie_{}", job.submission.id),
-                        String::from("http://localhost:2375"),
-                    )
-                    .await
-                    {
-                        Ok(state) => state.exit_code,
-                        Err(_) => {
-                            job.status = JobStatus::Pending;
+    // Polling is the default, ravel can also push submissions or hold claims open
+    let intake = match config.intake.mode {
+        IntakeMode::Poll => Intake::Poll {
+            min: Duration::from_secs(config.intake.poll_min),
+            max: Duration::from_secs(config.intake.poll_max),
+        },
+        IntakeMode::LongPoll => Intake::LongPoll {
+            wait: Duration::from_secs(config.intake.long_poll_wait),
+        },
+        IntakeMode::Webhook => Intake::Webhook {
+            addr: config.intake.webhook_addr.clone(),
+            secret: config.intake.webhook_secret.clone().unwrap_or_default(),
+            poll: Duration::from_secs(config.intake.poll_max),
+        },
+    };
 
-                            let _ =
-                                tokio::fs::remove_dir_all(format!("./jobs/{}", job.submission.id))
-                                    .await;
+    let (state, records) =
+        StateStore::open(&config.paths.state_dir).context("Unable to open job state")?;
+    let outbox = Outbox::open(&config.paths.state_dir).context("Unable to open result outbox")?;
 
-                            match rm_container(
-                                format!("reverie_{}", job.submission.id),
-                                String::from("http://localhost:2375"),
-                            )
-                            .await
-                            {
-                                Ok(_) => {}
-                                // TODO: Cleanup containers that couldn't be removed later
-                                Err(_) => {
-                                    error!(
-                                        "Unable to remove container reverie_{}",
-                                        job.submission.id
-                                    )
-                                }
-                            }
-                            -1
-                        }
-                    };
-                    let result = JobResult::from_i32(exit_code);
+    let source = source::connect(&config)?;
 
-                    println!("{:?}", result);
+    let (progress_tx, progress_rx) = mpsc::channel(max_jobs * 16);
+    let judge = Judge {
+        source,
+        judge_id: Arc::new(judge_id),
+        active: Arc::new(Mutex::new(HashSet::new())),
+        resources: ResourcePool::new(judging_cores, total_memory, config.resources.pin_cpus),
+        state: Arc::new(state),
+        outbox: Arc::new(outbox),
+        progress: progress_tx,
+        mode: Arc::new(watch::channel(Mode::Serving).0),
+        readiness: Arc::new(Mutex::new(Readiness::default())),
+        config: Arc::new(watch::channel(Arc::new(config.clone())).0),
+    };
 
-                    info!("Removing container reverie_{}", job.submission.id);
-                    match rm_container(
-                        format!("reverie_{}", job.submission.id),
-                        String::from("http://localhost:2375"),
-                    )
-                    .await
-                    {
-                        Ok(_) => {}
-                        // TODO: Cleanup containers that couldn't be removed later
-                        Err(_) => {
-                            error!("Unable to remove container reverie_{}", job.submission.id)
-                        }
-                    }
+    let (queue_tx, queue_rx) = mpsc::channel(max_jobs);
 
-                    let mut solved = true;
-                    let mut err = None;
-                    if result != Some(Correct) {
-                        solved = false;
-                        err = result;
-                    }
+    scheduler::recover(&judge, records, queue_tx.clone());
+    let janitor = tokio::spawn(janitor::run(
+        judge.clone(),
+        Duration::from_secs(config.docker.janitor_interval),
+    ));
+    tokio::spawn(reload::run(judge.clone(), args, config_path));
+    tokio::spawn(prefetch::run(
+        judge.clone(),
+        Duration::from_secs(config.cache.prefetch_interval),
+    ));
+    if let Some(addr) = config.cache.status_addr.clone() {
+        tokio::spawn(prefetch::serve_status(judge.clone(), addr));
+    }
+    tokio::spawn(intake::run(judge.clone(), queue_tx, intake));
+    tokio::spawn(scheduler::heartbeat(
+        judge.clone(),
+        Duration::from_secs(config.lease_heartbeat),
+    ));
+    tokio::spawn(scheduler::report_progress(judge.clone(), progress_rx));
+    let reporter = tokio::spawn(scheduler::report_results(judge.clone()));
+    let dispatcher = tokio::spawn(scheduler::dispatch(
+        judge.clone(),
+        queue_rx,
+        Duration::from_secs(config.shutdown_timeout),
+    ));
 
-                    info!(
-                        "Submission '{}' has finished with the result solved: '{}', err: '{:?}'",
-                        job.submission.id, solved, err
-                    );
+    signals::wait_for_shutdown(&judge).await;
 
-                    finished.submissions.push(ravel::FinishedSubmissions {
-                        id: job.submission.id,
-                        solved,
-                        error: err,
-                    });
-                }
-            }
-        }
+    let _ = dispatcher.await;
+    reporter.abort();
+    info!("All jobs have stopped, flushing results to ravel");
+    // Results still unsent stay in the outbox and are posted after a restart
+    if tokio::time::timeout(Duration::from_secs(30), scheduler::flush(&judge))
+        .await
+        .is_err()
+    {
+        error!("Unable to deliver every result to ravel before exiting");
+    }
 
-        if finished.submissions.len() > 0 {
-            match client
-                .post(format!("{}/judge/update", url))
-                .json(&finished)
-                .send()
-                .await
-            {
-                Ok(_) => {
-                    for job in &finished.submissions {
-                        let _ = tokio::fs::remove_dir_all(format!("./jobs/{}", job.id)).await;
-                        jobs.remove(&job.id);
-                    }
-                    finished.submissions.clear();
-                }
-                Err(err) => {
-                    error!("Unable to update submissions on ravel, with error: {}", err);
-                }
-            }
-        }
+    janitor.abort();
+    janitor::sweep(&judge).await;
+    if let Err(err) = judge.state.flush().await {
+        error!("Unable to persist job state before exiting: {:#}", err);
     }
+    info!("Shutdown complete");
+    Ok(())
 }
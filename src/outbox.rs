@@ -0,0 +1,99 @@
+use crate::source::FinishedSubmissions;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::sync::{self, Notify};
+
+/// Verdicts waiting to be acknowledged by ravel, kept on disk so they survive a
+/// restart. Holds at most one verdict per submission.
+pub struct Outbox {
+    path: PathBuf,
+    pending: Mutex<BTreeMap<i32, FinishedSubmissions>>,
+    // Held while writing so an older snapshot never replaces a newer one
+    writing: sync::Mutex<()>,
+    added: Notify,
+}
+
+impl Outbox {
+    pub fn open(dir: &Path) -> Result<Self> {
+        let path = dir.join("outbox.json");
+        let pending = if path.exists() {
+            let contents = fs::read_to_string(&path)
+                .with_context(|| format!("Unable to read {}", path.display()))?;
+            serde_json::from_str::<Vec<FinishedSubmissions>>(&contents)
+                .with_context(|| format!("Unable to parse {}", path.display()))?
+                .into_iter()
+                .map(|result| (result.id, result))
+                .collect()
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(Outbox {
+            path,
+            pending: Mutex::new(pending),
+            writing: sync::Mutex::new(()),
+            added: Notify::new(),
+        })
+    }
+
+    /// Queues a verdict for delivery, replacing any earlier verdict for the same submission.
+    pub async fn push(&self, result: FinishedSubmissions) -> Result<()> {
+        self.pending.lock().unwrap().insert(result.id, result);
+        self.persist().await?;
+        self.added.notify_one();
+        Ok(())
+    }
+
+    /// Removes the verdicts ravel has acknowledged.
+    pub async fn acknowledge(&self, ids: &[i32]) -> Result<()> {
+        {
+            let mut pending = self.pending.lock().unwrap();
+            for id in ids {
+                pending.remove(id);
+            }
+        }
+        self.persist().await
+    }
+
+    pub fn pending(&self) -> Vec<FinishedSubmissions> {
+        self.pending.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn contains(&self, id: i32) -> bool {
+        self.pending.lock().unwrap().contains_key(&id)
+    }
+
+    /// Waits until there is at least one verdict to deliver.
+    pub async fn wait(&self) {
+        loop {
+            let added = self.added.notified();
+            if !self.pending.lock().unwrap().is_empty() {
+                return;
+            }
+            added.await;
+        }
+    }
+
+    // Written to a temporary file and renamed so a crash never leaves a torn outbox.
+    // The snapshot is taken once it's our turn to write, so it is never older than
+    // the one before it.
+    async fn persist(&self) -> Result<()> {
+        let _writing = self.writing.lock().await;
+        let contents =
+            serde_json::to_vec(&self.pending.lock().unwrap().values().collect::<Vec<_>>())?;
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || {
+            let tmp = path.with_extension("json.tmp");
+            let mut file = File::create(&tmp)
+                .with_context(|| format!("Unable to create {}", tmp.display()))?;
+            file.write_all(&contents)?;
+            file.sync_all()?;
+            fs::rename(&tmp, &path).with_context(|| format!("Unable to replace {}", path.display()))
+        })
+        .await?
+    }
+}
@@ -0,0 +1,151 @@
+use crate::problem::{
+    Checker, CheckerKind, Limits, LocalProblem, Solution, TestCase, Validator, ValidatorKind,
+};
+use crate::runner::JobResult;
+use anyhow::{bail, Context, Result};
+use roxmltree::{Document, Node};
+use std::fs;
+use std::path::Path;
+
+/// Reads a Codeforces Polygon package described by `problem.xml`. Only the
+/// first testset is judged, and generated tests have to be in the package
+/// (the "full" package Polygon builds).
+pub fn load(dir: &Path) -> Result<LocalProblem> {
+    let xml_path = dir.join("problem.xml");
+    let xml = fs::read_to_string(&xml_path)
+        .with_context(|| format!("Unable to read {}", xml_path.display()))?;
+    let document =
+        Document::parse(&xml).with_context(|| format!("Unable to parse {}", xml_path.display()))?;
+    let root = document.root_element();
+
+    let testset = descendant(root, "testset").context("problem.xml has no testset")?;
+    // Milliseconds
+    let time_limit: u64 = child_text(testset, "time-limit")
+        .context("Testset has no time-limit")?
+        .parse()
+        .context("Unable to parse time-limit")?;
+    // Bytes
+    let memory_limit: Option<u64> = child_text(testset, "memory-limit")
+        .map(|memory| memory.parse().context("Unable to parse memory-limit"))
+        .transpose()?;
+
+    let input_pattern =
+        child_text(testset, "input-path-pattern").context("Testset has no input-path-pattern")?;
+    let answer_pattern =
+        child_text(testset, "answer-path-pattern").context("Testset has no answer-path-pattern")?;
+    let count = match child(testset, "tests") {
+        Some(tests) => tests
+            .children()
+            .filter(|test| test.has_tag_name("test"))
+            .count(),
+        None => child_text(testset, "test-count")
+            .context("Testset has no tests")?
+            .parse()
+            .context("Unable to parse test-count")?,
+    };
+
+    let mut tests = Vec::new();
+    for number in 1..=count {
+        let input = dir.join(format_path(&input_pattern, number));
+        let output = dir.join(format_path(&answer_pattern, number));
+        if !input.exists() || !output.exists() {
+            bail!(
+                "Test {} is missing from the package, download the full package with generated tests",
+                number
+            );
+        }
+        tests.push(TestCase {
+            name: format_path("%02d", number),
+            input,
+            output,
+        });
+    }
+
+    let mut solutions = Vec::new();
+    if let Some(list) = descendant(root, "solutions") {
+        for solution in list.children().filter(|node| node.has_tag_name("solution")) {
+            // Tags without a matching verdict, like rejected or failed, aren't checked
+            let expected = match solution.attribute("tag") {
+                Some("main") | Some("accepted") => JobResult::Correct,
+                Some("wrong-answer") | Some("presentation-error") => JobResult::Wrong,
+                Some("time-limit-exceeded") => JobResult::TimelimitException,
+                _ => continue,
+            };
+            if let Some(path) = source_path(solution) {
+                solutions.push(Solution {
+                    path: dir.join(path),
+                    expected,
+                });
+            }
+        }
+    }
+
+    let validators = match descendant(root, "validators") {
+        Some(list) => list
+            .children()
+            .filter(|node| node.has_tag_name("validator"))
+            .filter_map(source_path)
+            .map(|path| Validator {
+                path: dir.join(path),
+                kind: ValidatorKind::Testlib,
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    let checker = descendant(root, "checker")
+        .and_then(source_path)
+        .map(|path| Checker {
+            path: dir.join(path),
+            kind: CheckerKind::Testlib,
+            flags: Vec::new(),
+        });
+
+    Ok(LocalProblem {
+        limits: Limits {
+            time_limit,
+            memory: memory_limit.map(|bytes| bytes / 1024 / 1024),
+        },
+        tests,
+        solutions,
+        validators,
+        checker,
+        // Checkers and validators include the testlib.h shipped in files/
+        includes: vec![dir.join("files")],
+    })
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| child.has_tag_name(name))
+}
+
+fn descendant<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.descendants().find(|child| child.has_tag_name(name))
+}
+
+fn child_text(node: Node, name: &str) -> Option<String> {
+    child(node, name)
+        .and_then(|child| child.text())
+        .map(|text| text.trim().to_string())
+}
+
+fn source_path<'a>(node: Node<'a, '_>) -> Option<&'a str> {
+    child(node, "source").and_then(|source| source.attribute("path"))
+}
+
+// Fills in the printf style number in a path pattern like `tests/%02d`
+fn format_path(pattern: &str, number: usize) -> String {
+    let Some(start) = pattern.find('%') else {
+        return pattern.to_string();
+    };
+    let Some(end) = pattern[start..].find('d').map(|end| start + end) else {
+        return pattern.to_string();
+    };
+    let spec = &pattern[start + 1..end];
+    let width: usize = spec.trim_start_matches('0').parse().unwrap_or(0);
+    let number = if spec.starts_with('0') {
+        format!("{:0width$}", number, width = width)
+    } else {
+        format!("{:width$}", number, width = width)
+    };
+    format!("{}{}{}", &pattern[..start], number, &pattern[end + 1..])
+}
@@ -0,0 +1,124 @@
+use crate::cache;
+use crate::scheduler::Judge;
+use anyhow::{Context, Result};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// How warm the problem cache is, served on the status endpoint.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Readiness {
+    // Every active problem was cached and up to date after the last pass
+    pub ready: bool,
+    // Number of active problems
+    pub problems: usize,
+    // Active problems the last pass couldn't download or skipped to stay within the cache budget
+    pub missing: Vec<i32>,
+    // When the last pass finished, None until the first one does
+    pub checked: Option<DateTime<Utc>>,
+}
+
+/// Downloads missing or stale active problems at startup and then every `every`.
+pub async fn run(judge: Judge, every: Duration) {
+    let mut interval = tokio::time::interval(every);
+    loop {
+        interval.tick().await;
+        warm(&judge).await;
+    }
+}
+
+/// Makes sure every active problem is cached with the test data the source has now.
+pub async fn warm(judge: &Judge) {
+    let problems = match judge.source.active_problems().await {
+        Ok(problems) => problems,
+        Err(err) => {
+            error!("Unable to list problems to prefetch: {:#}", err);
+            return;
+        }
+    };
+
+    let config = judge.config();
+    let mut missing = Vec::new();
+    let mut skipped = Vec::new();
+    for problem in &problems {
+        match cache::prefetch(
+            &config.paths.work_dir,
+            judge.source.as_ref(),
+            problem.id,
+            &problem.input_sum,
+            &problem.output_sum,
+            config.cache_budget(),
+            config.cache.compress,
+        )
+        .await
+        {
+            Ok(true) => {}
+            Ok(false) => {
+                skipped.push(problem.id);
+                missing.push(problem.id);
+            }
+            Err(err) => {
+                error!("Unable to prefetch problem {}: {:#}", problem.id, err);
+                missing.push(problem.id);
+            }
+        }
+    }
+    if !skipped.is_empty() {
+        warn!(
+            "Not prefetching problems {:?}, the cache budget is used up",
+            skipped
+        );
+    }
+
+    let mut readiness = judge.readiness.lock().unwrap();
+    if missing.is_empty() && !readiness.ready {
+        info!(
+            "Problem cache is warm, all {} active problems are cached",
+            problems.len()
+        );
+    } else if !missing.is_empty() {
+        warn!(
+            "Problem cache is cold, problems {:?} are not cached",
+            missing
+        );
+    }
+    *readiness = Readiness {
+        ready: missing.is_empty(),
+        problems: problems.len(),
+        missing,
+        checked: Some(Utc::now()),
+    };
+}
+
+/// Serves `GET /ready` on `addr`, which answers 200 once the cache is warm and
+/// 503 until then, with the readiness as JSON either way.
+pub async fn serve_status(judge: Judge, addr: String) {
+    if let Err(err) = listen(judge, &addr).await {
+        error!("Status endpoint stopped: {:#}", err);
+    }
+}
+
+async fn listen(judge: Judge, addr: &str) -> Result<()> {
+    let app = Router::new().route("/ready", get(ready)).with_state(judge);
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Unable to listen on {}", addr))?;
+    info!("Serving readiness on http://{}/ready", addr);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn ready(State(judge): State<Judge>) -> (StatusCode, Json<Readiness>) {
+    let readiness = judge.readiness.lock().unwrap().clone();
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
@@ -0,0 +1,194 @@
+use crate::runner::JobResult;
+use crate::{kattis, polygon};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Deserializer};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Limits {
+    // Milliseconds per test, given in seconds as `timeout` in limits.json
+    #[serde(rename = "timeout", deserialize_with = "seconds")]
+    pub time_limit: u64,
+    // MB, the judge's job_memory when left out
+    #[serde(default)]
+    pub memory: Option<u64>,
+}
+
+// Reads a possibly fractional number of seconds as milliseconds
+fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    let seconds = f64::deserialize(deserializer)?;
+    Ok((seconds * 1000.0).round().max(0.0) as u64)
+}
+
+#[derive(Debug, Clone)]
+pub struct TestCase {
+    pub name: String,
+    pub input: PathBuf,
+    pub output: PathBuf,
+}
+
+// A solution shipped with the problem and the verdict it should get
+#[derive(Debug, Clone)]
+pub struct Solution {
+    pub path: PathBuf,
+    pub expected: JobResult,
+}
+
+// How a checker is called and reports its verdict
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CheckerKind {
+    // `checker input answer feedback_dir < output`, exits 42 to accept and 43 to reject
+    Kattis,
+    // `checker input output answer`, exits 0 to accept and 1 or 2 to reject
+    Testlib,
+}
+
+// Decides whether an output is correct when exact comparison isn't enough
+#[derive(Debug, Clone)]
+pub struct Checker {
+    pub path: PathBuf,
+    pub kind: CheckerKind,
+    // Passed after the checker's usual arguments, like Kattis' validator_flags
+    pub flags: Vec<String>,
+}
+
+// How an input validator says an input is valid
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ValidatorKind {
+    // Exits 42 to accept, like Kattis input validators
+    Kattis,
+    // Exits 0 to accept, like testlib validators
+    Testlib,
+}
+
+// Checks that a test input is well formed
+#[derive(Debug, Clone)]
+pub struct Validator {
+    pub path: PathBuf,
+    pub kind: ValidatorKind,
+}
+
+// Solution dirs under `submissions/` and the verdict their solutions should get
+const SOLUTION_DIRS: [(&str, JobResult); 5] = [
+    ("accepted", JobResult::Correct),
+    ("wrong_answer", JobResult::Wrong),
+    ("time_limit_exceeded", JobResult::TimelimitException),
+    ("run_time_error", JobResult::RuntimeError),
+    ("compile_error", JobResult::CompilerError),
+];
+
+/// A problem kept on disk. Besides Kattis/ICPC and Polygon packages the judge
+/// reads its own layout: `limits.json` next to a `tests/` dir holding each
+/// test's input as `<name>.in` and expected output as `<name>.ans`. Reference
+/// solutions live in `submissions/<verdict>/` and input validators in
+/// `input_validators/`, both optional.
+#[derive(Debug, Clone)]
+pub struct LocalProblem {
+    pub limits: Limits,
+    pub tests: Vec<TestCase>,
+    pub solutions: Vec<Solution>,
+    pub validators: Vec<Validator>,
+    pub checker: Option<Checker>,
+    // Extra include dirs for compiling validators and checkers, e.g. for testlib.h
+    pub includes: Vec<PathBuf>,
+}
+
+impl LocalProblem {
+    /// Loads a problem in any supported format. Every path in the result is absolute.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let dir = dir
+            .canonicalize()
+            .with_context(|| format!("Unable to find problem {}", dir.display()))?;
+        if dir.join("problem.xml").exists() {
+            polygon::load(&dir)
+        } else if dir.join("problem.yaml").exists() {
+            kattis::load(&dir)
+        } else {
+            load_native(&dir)
+        }
+    }
+}
+
+fn load_native(dir: &Path) -> Result<LocalProblem> {
+    let limits_path = dir.join("limits.json");
+    let limits = fs::read_to_string(&limits_path)
+        .with_context(|| format!("Unable to read {}", limits_path.display()))?;
+    let limits: Limits = serde_json::from_str(&limits)
+        .with_context(|| format!("Unable to parse {}", limits_path.display()))?;
+
+    let tests_dir = dir.join("tests");
+    let tests = test_cases(&tests_dir, &tests_dir)?;
+    if tests.is_empty() {
+        bail!("No tests found in {}", tests_dir.display());
+    }
+
+    Ok(LocalProblem {
+        limits,
+        tests,
+        solutions: solutions(dir)?,
+        validators: files(&dir.join("input_validators"))?
+            .into_iter()
+            .map(|path| Validator {
+                path,
+                kind: ValidatorKind::Kattis,
+            })
+            .collect(),
+        checker: None,
+        includes: Vec::new(),
+    })
+}
+
+/// Every `<name>.in` with a matching `<name>.ans` directly in `dir`, sorted and
+/// named relative to `root`.
+pub fn test_cases(dir: &Path, root: &Path) -> Result<Vec<TestCase>> {
+    let mut tests = Vec::new();
+    for input in files(dir)? {
+        if input.extension().is_none_or(|ext| ext != "in") {
+            continue;
+        }
+        let output = input.with_extension("ans");
+        if !output.exists() {
+            bail!("Test {} has no matching .ans file", input.display());
+        }
+        let name = input
+            .strip_prefix(root)
+            .unwrap_or(&input)
+            .with_extension("")
+            .to_string_lossy()
+            .into_owned();
+        tests.push(TestCase {
+            name,
+            input,
+            output,
+        });
+    }
+    Ok(tests)
+}
+
+/// Solutions sorted into `submissions/<verdict>/`.
+pub fn solutions(dir: &Path) -> Result<Vec<Solution>> {
+    let mut solutions = Vec::new();
+    for (name, expected) in SOLUTION_DIRS {
+        for path in files(&dir.join("submissions").join(name))? {
+            solutions.push(Solution { path, expected });
+        }
+    }
+    Ok(solutions)
+}
+
+/// Files directly inside `dir` sorted by name, nothing if it doesn't exist.
+pub fn files(dir: &Path) -> Result<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir).with_context(|| format!("Unable to read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
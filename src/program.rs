@@ -0,0 +1,73 @@
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+/// A helper program shipped with a problem, like an input validator or a
+/// checker. These are trusted and run on the host rather than in the sandbox.
+pub struct Program {
+    path: PathBuf,
+    // Used to run scripts, compiled and prebuilt programs run directly
+    interpreter: Option<&'static str>,
+}
+
+impl Program {
+    /// Gets `source` ready to run, compiling C++ into `out_dir`. The source's own
+    /// dir and `includes` are searched for headers.
+    pub async fn build(source: &Path, includes: &[PathBuf], out_dir: &Path) -> Result<Self> {
+        let extension = source
+            .extension()
+            .map(|ext| ext.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "py" => Ok(Program {
+                path: source.to_path_buf(),
+                interpreter: Some("python3"),
+            }),
+            "cpp" | "cc" | "cxx" => {
+                let binary = out_dir.join(format!(
+                    "{:x}",
+                    md5::compute(source.to_string_lossy().as_bytes())
+                ));
+                let mut compile = Command::new("g++");
+                compile.args(["-O2", "-std=gnu++17", "-o"]).arg(&binary);
+                if let Some(parent) = source.parent() {
+                    compile.arg("-I").arg(parent);
+                }
+                for include in includes {
+                    compile.arg("-I").arg(include);
+                }
+                let output = compile
+                    .arg(source)
+                    .output()
+                    .await
+                    .context("Unable to run g++")?;
+                if !output.status.success() {
+                    bail!(
+                        "Unable to compile {}:\n{}",
+                        source.display(),
+                        String::from_utf8_lossy(&output.stderr)
+                    );
+                }
+                Ok(Program {
+                    path: binary,
+                    interpreter: None,
+                })
+            }
+            _ => Ok(Program {
+                path: source.to_path_buf(),
+                interpreter: None,
+            }),
+        }
+    }
+
+    pub fn command(&self) -> Command {
+        match self.interpreter {
+            Some(interpreter) => {
+                let mut command = Command::new(interpreter);
+                command.arg(&self.path);
+                command
+            }
+            None => Command::new(&self.path),
+        }
+    }
+}
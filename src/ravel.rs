@@ -1,68 +1,534 @@
+use crate::archive::{Archive, Compression};
+use crate::cache::{Part, ProblemSink};
 use crate::error::Errors;
-use crate::{runner, Languages};
+use crate::jsonstream::StringFields;
+use crate::runner::JobResult;
+use crate::source::{FinishedSubmissions, Problem, Progress, Submission};
 use anyhow::{anyhow, Context, Result};
-use reqwest::Client;
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::warn;
 
-#[derive(Deserialize, Debug, Clone)]
-pub struct Submission {
-    // Submission id
+/// A problem open for submissions, with the checksums of its test data.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ActiveProblem {
     pub id: i32,
-    // Time code is in
-    pub language: Languages,
-    // Code
-    pub content: String,
-    // Problem id
-    pub problem: i32,
-    // Input file checksum
     pub input_sum: String,
-    // Output file checksum
     pub output_sum: String,
-    // Timelimit
-    pub timeout: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct ActiveProblems {
+    problems: Vec<ActiveProblem>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Submissions {
-    submissions: Vec<Submission>,
+    pub submissions: Vec<Submission>,
 }
 
 #[derive(Serialize, Debug, Clone)]
 pub struct Update {
-    pub username: String,
-    pub password: String,
-    pub submissions: Vec<FinishedSubmissions>,
+    pub submissions: Vec<Verdict>,
 }
 
+// The part of a finished submission ravel records
 #[derive(Serialize, Debug, Clone)]
-pub struct FinishedSubmissions {
+pub struct Verdict {
     pub id: i32,
     pub solved: bool,
-    pub error: Option<runner::JobResult>,
-}
-
-pub async fn get_submissions(
-    creds: &HashMap<&str, String>,
-    client: &Client,
-    url: &String,
-) -> Result<Vec<Submission>> {
-    let res = client
-        .get(format!("{}/judge/pending", url))
-        .header("Content-Type", "application/json")
-        .json(&creds)
-        .send()
-        .await?;
-
-    return match res.status() {
-        reqwest::StatusCode::OK => match res.json::<Submissions>().await {
-            Ok(parsed) => Ok(parsed.submissions),
-            Err(err) => {
-                println!("{}", err);
-                return Err(anyhow!(Errors::SubmissionFetchError))
-                    .context("Error parsing submissions");
+    pub error: Option<JobResult>,
+}
+
+impl From<&FinishedSubmissions> for Verdict {
+    fn from(result: &FinishedSubmissions) -> Self {
+        Verdict {
+            id: result.id,
+            solved: result.solved,
+            error: result.error,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct ProgressUpdate<'a> {
+    judge: &'a str,
+    id: i32,
+    #[serde(flatten)]
+    progress: Progress,
+}
+
+#[derive(Serialize, Debug)]
+struct ClaimRequest<'a> {
+    judge: &'a str,
+    count: usize,
+    // Seconds ravel may hold the request open waiting for submissions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wait: Option<u64>,
+}
+
+#[derive(Serialize, Debug)]
+struct LeaseRequest<'a> {
+    judge: &'a str,
+    submissions: &'a [i32],
+}
+
+#[derive(Deserialize, Debug)]
+struct Acknowledged {
+    acknowledged: Vec<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Leases {
+    submissions: Vec<i32>,
+}
+
+pub enum Credentials {
+    // Exchanged for a token at login, only ever sent to /judge/login
+    Password { username: String, password: String },
+    // Static key sent as the bearer token
+    ApiKey(String),
+}
+
+#[derive(Serialize, Debug)]
+struct Login<'a> {
+    username: &'a str,
+    password: &'a str,
+}
+
+#[derive(Deserialize, Debug)]
+struct Token {
+    token: String,
+}
+
+// How requests that fail for transient reasons are retried
+pub struct RetryPolicy {
+    pub attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    // Exponential backoff with full jitter
+    fn delay(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        ceiling.mul_f64(rand::random::<f64>())
+    }
+}
+
+/// Client for ravel's judge API. Logs in on first use and again whenever ravel
+/// rejects the current token, and retries transient failures with backoff.
+pub struct RavelClient {
+    client: Client,
+    url: String,
+    credentials: Credentials,
+    token: RwLock<Option<String>>,
+    timeout: Duration,
+    retry: RetryPolicy,
+}
+
+impl RavelClient {
+    pub fn new(
+        url: String,
+        credentials: Credentials,
+        timeout: Duration,
+        retry: RetryPolicy,
+    ) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(timeout)
+            .connect_timeout(timeout)
+            .build()
+            .context("Unable to build ravel http client")?;
+        Ok(RavelClient {
+            client,
+            url,
+            credentials,
+            token: RwLock::new(None),
+            timeout,
+            retry,
+        })
+    }
+
+    /// Leases up to `count` pending submissions to this judge. Ravel hands each
+    /// submission to only one judge and puts it back to pending if the lease expires.
+    pub async fn pending(&self, judge: &str, count: usize) -> Result<Vec<Submission>> {
+        let res = self
+            .send(false, |client| {
+                client
+                    .post(format!("{}/judge/claim", self.url))
+                    .json(&ClaimRequest {
+                        judge,
+                        count,
+                        wait: None,
+                    })
+            })
+            .await
+            .context("Unable to claim submissions")?;
+        Ok(res
+            .json::<Submissions>()
+            .await
+            .map_err(|_| anyhow!(Errors::SubmissionFetchError))?
+            .submissions)
+    }
+
+    /// Like `pending`, but ravel holds the request open for up to `wait` until
+    /// submissions are available instead of answering straight away.
+    pub async fn pending_wait(
+        &self,
+        judge: &str,
+        count: usize,
+        wait: Duration,
+    ) -> Result<Vec<Submission>> {
+        let res = self
+            .send(false, |client| {
+                client
+                    .post(format!("{}/judge/claim", self.url))
+                    .timeout(self.timeout + wait)
+                    .json(&ClaimRequest {
+                        judge,
+                        count,
+                        wait: Some(wait.as_secs()),
+                    })
+            })
+            .await
+            .context("Unable to claim submissions")?;
+        Ok(res
+            .json::<Submissions>()
+            .await
+            .map_err(|_| anyhow!(Errors::SubmissionFetchError))?
+            .submissions)
+    }
+
+    /// Extends the leases on `submissions`, returning the ids this judge still holds.
+    pub async fn renew(&self, judge: &str, submissions: &[i32]) -> Result<Vec<i32>> {
+        let res = self
+            .send(true, |client| {
+                client
+                    .post(format!("{}/judge/heartbeat", self.url))
+                    .json(&LeaseRequest { judge, submissions })
+            })
+            .await
+            .context("Unable to renew leases")?;
+        Ok(res
+            .json::<Leases>()
+            .await
+            .map_err(|_| anyhow!(Errors::LeaseFetchError))?
+            .submissions)
+    }
+
+    /// Sends a heartbeat for no submissions, which needs valid credentials and
+    /// renews nothing.
+    pub async fn check(&self, judge: &str) -> Result<()> {
+        self.send(true, |client| {
+            client
+                .post(format!("{}/judge/heartbeat", self.url))
+                .json(&LeaseRequest {
+                    judge,
+                    submissions: &[],
+                })
+        })
+        .await
+        .context("Unable to reach ravel")?;
+        Ok(())
+    }
+
+    /// Gives up the leases on `submissions` so another judge can take them.
+    pub async fn release(&self, judge: &str, submissions: &[i32]) -> Result<()> {
+        self.send(true, |client| {
+            client
+                .post(format!("{}/judge/release", self.url))
+                .json(&LeaseRequest { judge, submissions })
+        })
+        .await
+        .context("Unable to release leases")?;
+        Ok(())
+    }
+
+    pub async fn problem(&self, problem_id: i32) -> Result<Problem> {
+        let mut json = HashMap::new();
+        json.insert("problem", problem_id.to_string());
+        let res = self
+            .send(true, |client| {
+                client
+                    .get(format!("{}/judge/problem", self.url))
+                    .header("Content-Type", "application/json")
+                    .json(&json)
+            })
+            .await
+            .with_context(|| format!("Unable to fetch problem {}", problem_id))?;
+        res.json::<Problem>()
+            .await
+            .map_err(|_| anyhow!(Errors::ProblemFetchError))
+    }
+
+    /// Problems in running or upcoming contests, which are worth caching ahead of time.
+    pub async fn active_problems(&self) -> Result<Vec<ActiveProblem>> {
+        let res = self
+            .send(true, |client| {
+                client.get(format!("{}/judge/problems", self.url))
+            })
+            .await
+            .context("Unable to fetch active problems")?;
+        Ok(res
+            .json::<ActiveProblems>()
+            .await
+            .map_err(|_| anyhow!(Errors::ProblemListError))?
+            .problems)
+    }
+
+    /// Streams a problem into `sink` as the response arrives instead of parsing
+    /// the whole body at once. Ravel may answer with a compressed tar archive of
+    /// `input.txt` and `output.txt` instead of JSON.
+    pub async fn download(&self, problem_id: i32, sink: &mut ProblemSink) -> Result<()> {
+        let mut json = HashMap::new();
+        json.insert("problem", problem_id.to_string());
+        let res = self
+            .send(true, |client| {
+                client
+                    .get(format!("{}/judge/problem", self.url))
+                    .header("Content-Type", "application/json")
+                    .header(
+                        ACCEPT,
+                        "application/zstd, application/gzip, application/x-tar, application/json;q=0.5",
+                    )
+                    .json(&json)
+            })
+            .await
+            .with_context(|| format!("Unable to fetch problem {}", problem_id))?;
+
+        let compression = res
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .and_then(Compression::from_content_type);
+        match compression {
+            Some(compression) => unpack(problem_id, res, compression, sink).await,
+            None => decode(problem_id, res, sink).await,
+        }
+    }
+
+    /// Posts verdicts, returning the ids of the submissions ravel has recorded.
+    /// Ravel treats verdicts it already has as acknowledged, so resending is safe.
+    pub async fn update(&self, update: &Update) -> Result<Vec<i32>> {
+        let res = self
+            .send(true, |client| {
+                client
+                    .post(format!("{}/judge/update", self.url))
+                    .json(update)
+            })
+            .await
+            .context("Unable to post results")?;
+        Ok(res
+            .json::<Acknowledged>()
+            .await
+            .map_err(|_| anyhow!(Errors::AcknowledgementError))?
+            .acknowledged)
+    }
+
+    pub async fn progress(&self, judge: &str, id: i32, progress: Progress) -> Result<()> {
+        self.send(true, |client| {
+            client
+                .post(format!("{}/judge/progress", self.url))
+                .json(&ProgressUpdate {
+                    judge,
+                    id,
+                    progress,
+                })
+        })
+        .await
+        .with_context(|| format!("Unable to report progress of submission {}", id))?;
+        Ok(())
+    }
+
+    async fn login(&self, username: &str, password: &str) -> Result<String> {
+        let res = self
+            .retrying(true, |client| {
+                client
+                    .post(format!("{}/judge/login", self.url))
+                    .json(&Login { username, password })
+            })
+            .await
+            .context("Unable to log in to ravel")?;
+        Ok(res
+            .json::<Token>()
+            .await
+            .map_err(|_| anyhow!(Errors::LoginError))?
+            .token)
+    }
+
+    async fn token(&self) -> Result<String> {
+        let (username, password) = match &self.credentials {
+            Credentials::ApiKey(key) => return Ok(key.clone()),
+            Credentials::Password { username, password } => (username, password),
+        };
+        if let Some(token) = self.token.read().await.as_ref() {
+            return Ok(token.clone());
+        }
+
+        let mut token = self.token.write().await;
+        if let Some(token) = token.as_ref() {
+            return Ok(token.clone());
+        }
+        let fresh = self.login(username, password).await?;
+        *token = Some(fresh.clone());
+        Ok(fresh)
+    }
+
+    // Logs in again unless another request already replaced the rejected token
+    async fn refresh(&self, rejected: &str) -> Result<Option<String>> {
+        let Credentials::Password { username, password } = &self.credentials else {
+            return Ok(None);
+        };
+        let mut token = self.token.write().await;
+        if let Some(current) = token.as_ref() {
+            if current != rejected {
+                return Ok(Some(current.clone()));
+            }
+        }
+        let fresh = self.login(username, password).await?;
+        *token = Some(fresh.clone());
+        Ok(Some(fresh))
+    }
+
+    /// Sends the request built by `request` with the client's token, logging in
+    /// again once if ravel responds with 401. Requests that aren't `idempotent`
+    /// are only retried when they never reached ravel.
+    async fn send(
+        &self,
+        idempotent: bool,
+        request: impl Fn(&Client) -> RequestBuilder,
+    ) -> Result<Response> {
+        let token = self.token().await?;
+        match self
+            .retrying(idempotent, |client| request(client).bearer_auth(&token))
+            .await
+        {
+            Err(err) if matches!(err.downcast_ref::<Errors>(), Some(Errors::Unauthorized)) => {
+                match self.refresh(&token).await? {
+                    Some(token) => {
+                        self.retrying(idempotent, |client| request(client).bearer_auth(&token))
+                            .await
+                    }
+                    None => Err(err),
+                }
+            }
+            other => other,
+        }
+    }
+
+    // Sends a request, retrying connection failures, and for idempotent requests
+    // also timeouts and 408/429/5xx responses, which ravel may have acted on.
+    // Any other non-2xx status is turned into an error.
+    async fn retrying(
+        &self,
+        idempotent: bool,
+        request: impl Fn(&Client) -> RequestBuilder,
+    ) -> Result<Response> {
+        let mut attempt = 0;
+        loop {
+            let err = match request(&self.client).send().await {
+                Ok(res) if res.status().is_success() => return Ok(res),
+                Ok(res) if res.status() == StatusCode::UNAUTHORIZED => {
+                    return Err(anyhow!(Errors::Unauthorized))
+                }
+                Ok(res) => {
+                    let status = res.status();
+                    let err = anyhow!(Errors::UnexpectedStatus(status.as_u16()));
+                    if !idempotent
+                        || !(status.is_server_error()
+                            || status == StatusCode::TOO_MANY_REQUESTS
+                            || status == StatusCode::REQUEST_TIMEOUT)
+                    {
+                        return Err(err);
+                    }
+                    err
+                }
+                Err(err) => {
+                    if !(err.is_connect() || (idempotent && err.is_timeout())) {
+                        return Err(anyhow!(err).context(Errors::RavelError));
+                    }
+                    anyhow!(err).context(Errors::RavelError)
+                }
+            };
+
+            attempt += 1;
+            if attempt >= self.retry.attempts {
+                return Err(err);
             }
-        },
-        _other => Err(anyhow!(Errors::RavelError)).context("Unable to retrieve submissions"),
-    };
+            let delay = self.retry.delay(attempt);
+            warn!(
+                "Request to ravel failed ({:#}), retrying in {}ms",
+                err,
+                delay.as_millis()
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+// Reads a problem sent as JSON with the data in the problem_input and
+// problem_output fields
+async fn decode(problem_id: i32, mut res: Response, sink: &mut ProblemSink) -> Result<()> {
+    let mut fields = StringFields::new();
+    while let Some(chunk) = res
+        .chunk()
+        .await
+        .with_context(|| format!("Download of problem {} was cut off", problem_id))?
+    {
+        for (key, bytes) in fields.feed(&chunk).context(Errors::ProblemFetchError)? {
+            match key.as_str() {
+                "problem_input" => sink.write(Part::Input, &bytes).await?,
+                "problem_output" => sink.write(Part::Output, &bytes).await?,
+                _ => {}
+            }
+        }
+    }
+    if !fields.finished() {
+        return Err(anyhow!(Errors::ProblemFetchError))
+            .with_context(|| format!("Download of problem {} was cut off", problem_id));
+    }
+    Ok(())
+}
+
+// Reads a problem sent as a compressed tar archive holding input.txt and
+// output.txt. Missing files show up as checksum mismatches once it is stored.
+async fn unpack(
+    problem_id: i32,
+    mut res: Response,
+    compression: Compression,
+    sink: &mut ProblemSink,
+) -> Result<()> {
+    let mut archive = Archive::new(compression)?;
+    while let Some(chunk) = res
+        .chunk()
+        .await
+        .with_context(|| format!("Download of problem {} was cut off", problem_id))?
+    {
+        let pieces = archive
+            .feed(&chunk)
+            .with_context(|| format!("Unable to unpack problem {}", problem_id))?;
+        write_files(pieces, sink).await?;
+    }
+    let pieces = archive
+        .finish()
+        .with_context(|| format!("Unable to unpack problem {}", problem_id))?;
+    write_files(pieces, sink).await
+}
+
+async fn write_files(pieces: Vec<(String, Vec<u8>)>, sink: &mut ProblemSink) -> Result<()> {
+    for (path, bytes) in pieces {
+        match path.as_str() {
+            "input.txt" => sink.write(Part::Input, &bytes).await?,
+            "output.txt" => sink.write(Part::Output, &bytes).await?,
+            _ => {}
+        }
+    }
+    Ok(())
 }
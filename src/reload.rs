@@ -0,0 +1,180 @@
+use crate::config::{Config, ConfigArgs};
+use crate::scheduler::Judge;
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info, warn};
+
+// How often the config file is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+// Settings that are read each time a job starts or the janitor sweeps. The rest
+// are only read at startup, so changing them needs a restart.
+const RELOADABLE: [&str; 10] = [
+    "max_jobs",
+    "docker.image",
+    "docker.kill_timeout",
+    "docker.poll_interval",
+    "docker.container_max_age",
+    "resources.job_cores",
+    "resources.job_memory",
+    "cache.budget",
+    "cache.compress",
+    "languages",
+];
+
+/// Reloads the configuration on SIGHUP and whenever the config file at `path`
+/// changes. `args` has to hold absolute paths, the judge runs in its work dir.
+pub async fn run(judge: Judge, args: ConfigArgs, path: Option<PathBuf>) {
+    let mut hangup = signal(SignalKind::hangup()).expect("Unable to listen for SIGHUP");
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+    let mut modified = path.as_deref().and_then(modified_at);
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => info!("Reloading configuration on SIGHUP"),
+            _ = interval.tick() => {
+                let current = path.as_deref().and_then(modified_at);
+                if current == modified {
+                    continue;
+                }
+                modified = current;
+                info!("Config file changed, reloading configuration");
+            }
+        }
+        if let Err(err) = reload(&judge, &args) {
+            error!(
+                "Unable to reload configuration, keeping the current one: {:#}",
+                err
+            );
+        }
+    }
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).ok()?.modified().ok()
+}
+
+/// Loads the configuration again and puts the settings that can change while
+/// running into effect for jobs started from now on. Changes to other settings
+/// are logged and ignored until a restart.
+pub fn reload(judge: &Judge, args: &ConfigArgs) -> Result<()> {
+    let loaded = Config::load(args)?;
+    let current = judge.config();
+
+    let before = flatten(&current)?;
+    let mut after = flatten(&loaded)?;
+    let mut changes = Vec::new();
+    let keys: BTreeSet<String> = before.keys().chain(after.keys()).cloned().collect();
+    for key in keys {
+        let (old, new) = (before.get(&key), after.get(&key));
+        if old == new {
+            continue;
+        }
+        if reloadable(&key) {
+            changes.push(format!(
+                "{}: {} -> {}",
+                key,
+                show(&key, old),
+                show(&key, new)
+            ));
+            continue;
+        }
+        warn!(
+            "{} changed from {} to {}, which only takes effect after a restart",
+            key,
+            show(&key, old),
+            show(&key, new)
+        );
+        match old {
+            Some(old) => after.insert(key, old.clone()),
+            None => after.remove(&key),
+        };
+    }
+
+    let config = unflatten(after)?;
+    config.validate()?;
+    if changes.is_empty() {
+        info!("Configuration reloaded, nothing that can be reloaded changed");
+        return Ok(());
+    }
+    for change in &changes {
+        info!("{}", change);
+    }
+    judge.config.send_replace(Arc::new(config));
+    info!(
+        "Configuration reloaded, {} settings changed for jobs started from now on",
+        changes.len()
+    );
+    Ok(())
+}
+
+fn reloadable(key: &str) -> bool {
+    RELOADABLE.iter().any(|prefix| {
+        key == *prefix
+            || key
+                .strip_prefix(prefix)
+                .is_some_and(|rest| rest.starts_with('.'))
+    })
+}
+
+// Keys that hold passwords or tokens
+fn secret(key: &str) -> bool {
+    key.ends_with("password") || key.ends_with("api_key") || key.ends_with("secret")
+}
+
+fn show(key: &str, value: Option<&toml::Value>) -> String {
+    match value {
+        None => String::from("unset"),
+        Some(_) if secret(key) => String::from("<redacted>"),
+        Some(value) => value.to_string(),
+    }
+}
+
+// Every setting by its dotted path, like docker.image
+fn flatten(config: &Config) -> Result<BTreeMap<String, toml::Value>> {
+    let mut settings = BTreeMap::new();
+    let toml::Value::Table(table) = toml::Value::try_from(config)? else {
+        unreachable!("Config serializes to a table");
+    };
+    flatten_table(String::new(), table, &mut settings);
+    Ok(settings)
+}
+
+fn flatten_table(prefix: String, table: toml::Table, settings: &mut BTreeMap<String, toml::Value>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            key
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match value {
+            toml::Value::Table(table) => flatten_table(key, table, settings),
+            value => {
+                settings.insert(key, value);
+            }
+        }
+    }
+}
+
+fn unflatten(settings: BTreeMap<String, toml::Value>) -> Result<Config> {
+    let mut root = toml::Table::new();
+    for (key, value) in settings {
+        let mut table = &mut root;
+        let mut parts: Vec<&str> = key.split('.').collect();
+        let last = parts.pop().unwrap_or_default();
+        for part in parts {
+            table = table
+                .entry(part)
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+                .context("Setting is both a value and a table")?;
+        }
+        table.insert(last.to_string(), value);
+    }
+    toml::Value::Table(root)
+        .try_into()
+        .context("Unable to rebuild the configuration")
+}
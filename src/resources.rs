@@ -0,0 +1,200 @@
+use anyhow::{anyhow, Context, Result};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+// Cores and memory (in MB) the judge is allowed to hand out to containers.
+// When pinning is enabled every core is given to at most one container at a time,
+// so a submission's timing doesn't depend on what else is running.
+pub struct ResourcePool {
+    state: Mutex<PoolState>,
+    released: Notify,
+    total_cores: usize,
+    total_memory: u64,
+    pin_cpus: bool,
+}
+
+struct PoolState {
+    free_cores: Vec<usize>,
+    free_memory: u64,
+}
+
+/// Resources held by a single job, returned to the pool when dropped.
+pub struct Reservation {
+    pool: Arc<ResourcePool>,
+    pub cores: Vec<usize>,
+    pub memory: u64,
+}
+
+impl ResourcePool {
+    pub fn new(mut cores: Vec<usize>, total_memory: u64, pin_cpus: bool) -> Arc<Self> {
+        // Kept in descending order so the lowest free core ids are handed out first
+        cores.sort_unstable_by(|a, b| b.cmp(a));
+        cores.dedup();
+        Arc::new(ResourcePool {
+            total_cores: cores.len(),
+            state: Mutex::new(PoolState {
+                free_cores: cores,
+                free_memory: total_memory,
+            }),
+            released: Notify::new(),
+            total_memory,
+            pin_cpus,
+        })
+    }
+
+    /// Waits until `cores` cores and `memory` MB are free and reserves them.
+    pub async fn reserve(self: &Arc<Self>, cores: usize, memory: u64) -> Result<Reservation> {
+        if cores > self.total_cores || memory > self.total_memory {
+            return Err(anyhow!(
+                "Job needs {} cores and {}MB but the judge only has {} cores and {}MB",
+                cores,
+                memory,
+                self.total_cores,
+                self.total_memory
+            ));
+        }
+
+        loop {
+            // Register interest before checking so a release in between is not missed
+            let released = self.released.notified();
+            if let Some(reservation) = self.try_reserve(cores, memory) {
+                return Ok(reservation);
+            }
+            released.await;
+        }
+    }
+
+    fn try_reserve(self: &Arc<Self>, cores: usize, memory: u64) -> Option<Reservation> {
+        let mut state = self.state.lock().unwrap();
+        if state.free_cores.len() < cores || state.free_memory < memory {
+            return None;
+        }
+        let at = state.free_cores.len() - cores;
+        let taken = state.free_cores.split_off(at);
+        state.free_memory -= memory;
+        Some(Reservation {
+            pool: self.clone(),
+            cores: taken,
+            memory,
+        })
+    }
+}
+
+impl Reservation {
+    /// Value for docker's `CpusetCpus`, only set when pinning is enabled.
+    pub fn cpuset(&self) -> Option<String> {
+        if !self.pool.pin_cpus {
+            return None;
+        }
+        Some(
+            self.cores
+                .iter()
+                .map(|core| core.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        )
+    }
+
+    pub fn nano_cpus(&self) -> i64 {
+        self.cores.len() as i64 * 1_000_000_000
+    }
+
+    pub fn memory_bytes(&self) -> i64 {
+        self.memory as i64 * 1024 * 1024
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let mut state = self.pool.state.lock().unwrap();
+        state.free_cores.append(&mut self.cores);
+        state.free_cores.sort_unstable_by(|a, b| b.cmp(a));
+        state.free_memory += self.memory;
+        drop(state);
+        self.pool.released.notify_waiters();
+    }
+}
+
+/// Parses a cpu list such as `0-3,6,8-9` into core ids.
+pub fn parse_cpu_list(list: &str) -> Result<Vec<usize>> {
+    let mut cores = Vec::new();
+    for part in list.trim().split(',').filter(|part| !part.is_empty()) {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let start: usize = start
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("Invalid cpu range '{}'", part))?;
+                let end: usize = end
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("Invalid cpu range '{}'", part))?;
+                cores.extend(start..=end);
+            }
+            None => cores.push(
+                part.trim()
+                    .parse()
+                    .with_context(|| format!("Invalid cpu '{}'", part))?,
+            ),
+        }
+    }
+    Ok(cores)
+}
+
+/// Ids of the cores online on the host.
+pub fn host_cores() -> Vec<usize> {
+    std::fs::read_to_string("/sys/devices/system/cpu/online")
+        .ok()
+        .and_then(|online| parse_cpu_list(&online).ok())
+        .filter(|cores| !cores.is_empty())
+        .unwrap_or_else(|| {
+            let count = std::thread::available_parallelism()
+                .map(|cores| cores.get())
+                .unwrap_or(1);
+            (0..count).collect()
+        })
+}
+
+/// Cores that are hyperthreads of the same physical core as `core`, including
+/// itself. Empty when the topology can't be read.
+pub fn siblings(core: usize) -> Vec<usize> {
+    std::fs::read_to_string(format!(
+        "/sys/devices/system/cpu/cpu{}/topology/thread_siblings_list",
+        core
+    ))
+    .ok()
+    .and_then(|list| parse_cpu_list(&list).ok())
+    .unwrap_or_default()
+}
+
+/// Pins every thread of the judge process to `cores`, keeping it off the
+/// cores used by submissions.
+pub fn pin_judge(cores: &[usize]) -> Result<()> {
+    // SAFETY: cpu_set_t is plain data and is only passed to libc by reference
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for core in cores {
+        unsafe { libc::CPU_SET(*core, &mut set) };
+    }
+
+    // Affinity is per thread, so apply it to all of the runtime's threads
+    for task in std::fs::read_dir("/proc/self/task").context("Unable to list judge threads")? {
+        let tid: libc::pid_t = match task?.file_name().to_string_lossy().parse() {
+            Ok(tid) => tid,
+            Err(_) => continue,
+        };
+        let res = unsafe { libc::sched_setaffinity(tid, std::mem::size_of_val(&set), &set) };
+        if res != 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Unable to pin judge thread {}", tid));
+        }
+    }
+    Ok(())
+}
+
+/// Total memory of the host in MB, read from /proc/meminfo.
+pub fn host_memory() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb / 1024)
+}
@@ -1,13 +1,20 @@
+use crate::cache::Part;
+use crate::config::Config;
 use crate::docker::{create_container, start_container, ContainerOptions};
-use crate::ravel::Submission;
+use crate::janitor::{JUDGE_LABEL, SUBMISSION_LABEL};
+use crate::resources::Reservation;
+use crate::source::SubmissionSource;
+use crate::source::{Progress, Submission};
 use crate::{cache, Languages};
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::env;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use tokio::fs;
-use tracing::{debug, error, info};
+use tracing::debug;
+
+// Bytes of each output file kept, the rest is cut off
+const OUTPUT_LIMIT: usize = 64 * 1024;
 
 #[derive(Deserialize, Serialize, Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub enum JobStatus {
@@ -40,100 +47,104 @@ impl JobResult {
     }
 }
 
+/// Prepares the job dir under `work_dir/jobs` and starts the container for a
+/// submission, returning the container id. The problem is cached in `work_dir/problems`.
 pub async fn run_submission(
+    work_dir: &Path,
     submission: Submission,
-    client: &reqwest::Client,
-    creds: &HashMap<&str, String>,
-    url: &String,
-) -> Result<()> {
-    // Cache problem info
-    match cache::check_cache(
-        &submission.problem,
-        submission.input_sum,
-        submission.output_sum,
+    source: &dyn SubmissionSource,
+    reservation: &Reservation,
+    judge_id: &str,
+    config: &Config,
+) -> Result<String> {
+    cache::ensure_cached(
+        work_dir,
+        source,
+        submission.problem,
+        &submission.input_sum,
+        &submission.output_sum,
+        config.cache_budget(),
+        config.cache.compress,
     )
-    .await
-    {
-        Ok(false) => {
-            info!("Problem {} is missing from cache", submission.problem);
-            cache::cache_problem(creds, client, url, submission.problem).await?;
-        }
-        Err(_) => {
-            error!("Unable to read problem {} from cache", submission.problem);
-            cache::cache_problem(creds, client, url, submission.problem).await?;
-        }
-        _ => {}
-    }
+    .await?;
 
-    if Path::exists(Path::new(&format!("./jobs/{}", submission.id))) {
-        fs::remove_dir_all(format!("./jobs/{}", submission.id))
-            .await
-            .with_context(|| {
-                format!(
-                    "Unable to remove existing dir for submission {}",
-                    submission.id
-                )
-            })?;
+    let dir = job_dir(work_dir, submission.id);
+    if dir.exists() {
+        fs::remove_dir_all(&dir).await.with_context(|| {
+            format!(
+                "Unable to remove existing dir for submission {}",
+                submission.id
+            )
+        })?;
     }
     // TODO: Lock dir once files are written
-    fs::create_dir(format!("./jobs/{}", submission.id))
+    fs::create_dir(&dir)
         .await
         .with_context(|| format!("Unable to create dir for submission {}", submission.id))?;
-    fs::copy(
-        format!("./problems/{}/input.txt", submission.problem),
-        format!("./jobs/{}/input.txt", submission.id),
+    cache::extract(
+        work_dir,
+        submission.problem,
+        Part::Input,
+        &dir.join("input.txt"),
     )
     .await
     .with_context(|| format!("Unable to copy input for submission {}", submission.id))?;
-    fs::copy(
-        format!("./problems/{}/output.txt", submission.problem),
-        format!("./jobs/{}/output.txt", submission.id),
+    cache::extract(
+        work_dir,
+        submission.problem,
+        Part::Output,
+        &dir.join("output.txt"),
     )
     .await
     .with_context(|| format!("Unable to copy input for submission {}", submission.id))?;
     match submission.language {
-        Languages::Python => fs::write(
-            format!("./jobs/{}/solution.py", submission.id),
-            submission.content,
-        ),
-        Languages::Java => fs::write(
-            format!("./jobs/{}/solution.java", submission.id),
-            submission.content,
-        ),
-        Languages::Cpp => fs::write(
-            format!("./jobs/{}/solution.cpp", submission.id),
-            submission.content,
-        ),
+        Languages::Python => fs::write(dir.join("solution.py"), submission.content),
+        Languages::Java => fs::write(dir.join("solution.java"), submission.content),
+        Languages::Cpp => fs::write(dir.join("solution.cpp"), submission.content),
     }
     .await?;
 
     // Lock job dir so user cant write to it
-    let mut perms = fs::metadata(format!("./jobs/{}", submission.id))
-      .await
-      .with_context(|| {
-          format!(
-              "Unable to get perms for job dir for problem {}.",
-              submission.id
-          )
-      })?
-      .permissions();
+    let mut perms = fs::metadata(&dir)
+        .await
+        .with_context(|| {
+            format!(
+                "Unable to get perms for job dir for problem {}.",
+                submission.id
+            )
+        })?
+        .permissions();
     perms.set_readonly(true);
 
     let mut binds = Vec::new();
     binds.push(format!(
-        "{}/jobs/{}:/usr/src/debussy",
-        env::current_dir()?.display(),
-        submission.id
+        "{}:/usr/src/debussy",
+        std::path::absolute(&dir)?.display()
     ));
+    let timeout_ms = config.timeout(
+        submission.language,
+        submission
+            .timeout_ms
+            .unwrap_or(submission.timeout.max(0) as u64 * 1000),
+    );
+    // TIMEOUT stays in whole seconds for images that don't read TIMEOUT_MS
     let mut env = Vec::new();
-    env.push(format!("TIMEOUT={}", submission.timeout));
+    env.push(format!("TIMEOUT={}", timeout_ms.div_ceil(1000)));
+    env.push(format!("TIMEOUT_MS={}", timeout_ms));
+
+    let mut labels = HashMap::new();
+    labels.insert(JUDGE_LABEL.to_string(), judge_id.to_string());
+    labels.insert(SUBMISSION_LABEL.to_string(), submission.id.to_string());
 
     let container_options = ContainerOptions {
-        image: "reverie_test".to_string(),
-        //image: "ghcr.io/timbercreekprogrammingteam/reverie:latest".to_string(),
+        image: config.docker.image.clone(),
         host_config: crate::docker::HostConfig {
             binds: Some(binds),
             auto_remove: false,
+            memory: Some(reservation.memory_bytes()),
+            nano_cpus: Some(reservation.nano_cpus()),
+            cpuset_cpus: reservation.cpuset(),
+            pids_limit: Some(PIDS_LIMIT),
         },
         tty: true,
         attach_stdin: true,
@@ -144,12 +155,13 @@ pub async fn run_submission(
         network_disabled: true,
         env: Some(env),
         volumes: None,
+        labels: Some(labels),
     };
 
-    create_container(
+    let container = create_container(
         container_options,
         format!("reverie_{}", submission.id),
-        String::from("http://localhost:2375"),
+        config.docker.url.clone(),
     )
     .await?;
 
@@ -160,7 +172,7 @@ pub async fn run_submission(
 
     start_container(
         format!("reverie_{}", submission.id),
-        String::from("http://localhost:2375"),
+        config.docker.url.clone(),
     )
     .await?;
 
@@ -169,5 +181,61 @@ pub async fn run_submission(
         submission.id
     );
 
-    Ok(())
+    Ok(container)
+}
+
+/// Most processes and threads a job's container may have, enough for the JVM and
+/// compilers but few enough that fork bombs fail.
+pub const PIDS_LIMIT: i64 = 256;
+
+/// What the judge image left in a job dir about a finished run. The image doesn't
+/// have to write any of it, the README lists the files.
+#[derive(Default, Debug)]
+pub struct RunFiles {
+    // The solution's stdout and stderr
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    // What the compiler printed
+    pub compiler: Option<String>,
+    // Milliseconds the solution ran, not counting compilation
+    pub time: Option<u64>,
+}
+
+/// Where the job for submission `id` runs, under the work dir `work_dir`.
+pub fn job_dir(work_dir: &Path, id: i32) -> PathBuf {
+    work_dir.join("jobs").join(id.to_string())
+}
+
+pub async fn read_run(dir: &Path) -> RunFiles {
+    let read = |name: &str| {
+        let path = dir.join(name);
+        async move {
+            let mut contents = fs::read(path).await.ok()?;
+            contents.truncate(OUTPUT_LIMIT);
+            Some(String::from_utf8_lossy(&contents).into_owned())
+        }
+    };
+    RunFiles {
+        stdout: read("stdout.txt").await,
+        stderr: read("stderr.txt").await,
+        compiler: read("compile.txt").await,
+        time: read("time.txt")
+            .await
+            .and_then(|time| time.trim().parse().ok()),
+    }
+}
+
+/// Reads the phase the judge image reports in the `progress.txt` of job dir
+/// `dir`, either `compiling` or `running <test>/<tests>`.
+pub async fn read_progress(dir: &Path) -> Option<Progress> {
+    let progress = fs::read_to_string(dir.join("progress.txt")).await.ok()?;
+    let progress = progress.trim();
+    if progress == "compiling" {
+        return Some(Progress::Compiling);
+    }
+    let (test, tests) = progress.strip_prefix("running ")?.split_once('/')?;
+    Some(Progress::Running {
+        test: test.trim().parse().ok()?,
+        tests: tests.trim().parse().ok()?,
+    })
 }
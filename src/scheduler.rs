@@ -0,0 +1,574 @@
+use crate::cache;
+use crate::config::Config;
+use crate::docker::{container_state, is_missing, kill_container, rm_container};
+use crate::outbox::Outbox;
+use crate::prefetch::Readiness;
+use crate::resources::{Reservation, ResourcePool};
+use crate::runner::JobResult::Correct;
+use crate::runner::{job_dir, read_progress, read_run, run_submission, JobResult, JobStatus};
+use crate::source::{FinishedSubmissions, Progress, Submission, SubmissionSource};
+use crate::state::{JobRecord, StateStore};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::{watch, OwnedSemaphorePermit, Semaphore};
+use tracing::{error, info, span, warn, Instrument};
+
+pub struct Job {
+    pub submission: Submission,
+    pub span: tracing::Span,
+    pub status: JobStatus,
+    pub container: Option<String>,
+    pub started: Option<DateTime<Utc>>,
+}
+
+impl Job {
+    fn new(submission: Submission) -> Self {
+        Job {
+            span: span!(tracing::Level::TRACE, "Submission", id = submission.id,),
+            submission,
+            status: JobStatus::Pending,
+            container: None,
+            started: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, PartialOrd, Ord)]
+pub enum Mode {
+    // Fetching and judging submissions
+    Serving,
+    // Finishing the jobs already taken without fetching new ones
+    Draining,
+    // Waiting for running jobs before exiting
+    ShuttingDown,
+    // The shutdown deadline passed, running jobs are killed
+    Aborting,
+}
+
+// State shared between the poller, the job tasks and the reporter
+#[derive(Clone)]
+pub struct Judge {
+    // Where submissions come from and verdicts go
+    pub source: Arc<dyn SubmissionSource>,
+    // Identifies this judge to the source when leasing submissions
+    pub judge_id: Arc<String>,
+    // Ids of every submission that is queued, running or waiting to be reported
+    pub active: Arc<Mutex<HashSet<i32>>>,
+    pub resources: Arc<ResourcePool>,
+    pub state: Arc<StateStore>,
+    pub outbox: Arc<Outbox>,
+    // Progress updates waiting to be sent to ravel
+    pub progress: Sender<(i32, Progress)>,
+    pub mode: Arc<watch::Sender<Mode>>,
+    // Whether every active problem has been prefetched
+    pub readiness: Arc<Mutex<Readiness>>,
+    // Settings in effect, replaced when the configuration is reloaded
+    pub config: Arc<watch::Sender<Arc<Config>>>,
+}
+
+impl Judge {
+    pub fn config(&self) -> Arc<Config> {
+        self.config.borrow().clone()
+    }
+
+    fn save(&self, job: &Job) {
+        let record = JobRecord {
+            submission: job.submission.clone(),
+            status: job.status,
+            container: job.container.clone(),
+            started: job.started,
+        };
+        // Checked under the lock so a release can't come in between and leave the
+        // record of a job that is already gone behind in the journal
+        let active = self.active.lock().unwrap();
+        if !active.contains(&job.submission.id) {
+            return;
+        }
+        if let Err(err) = self.state.update(record) {
+            error!(
+                "Unable to persist state of submission '{}': {}",
+                job.submission.id, err
+            );
+        }
+    }
+
+    /// Starts tracking a submission handed to this judge, returning `None` if it
+    /// already has it.
+    pub fn admit(&self, submission: Submission) -> Option<Job> {
+        if !self.active.lock().unwrap().insert(submission.id) {
+            return None;
+        }
+        let job = Job::new(submission);
+        self.save(&job);
+        self.report(job.submission.id, Progress::Queued);
+        Some(job)
+    }
+
+    // Progress is best effort, updates are dropped rather than holding up judging
+    fn report(&self, id: i32, progress: Progress) {
+        if self.progress.try_send((id, progress)).is_err() {
+            warn!("Dropping progress update for submission '{}'", id);
+        }
+    }
+
+    // Forgets a job entirely
+    pub fn release(&self, id: i32) {
+        let mut active = self.active.lock().unwrap();
+        active.remove(&id);
+        if let Err(err) = self.state.remove(id) {
+            error!("Unable to persist removal of submission '{}': {}", id, err);
+        }
+    }
+
+    // Forgets jobs and hands their leases back so any judge can take them again
+    pub async fn give_up(&self, ids: &[i32]) {
+        for id in ids {
+            self.release(*id);
+        }
+        if let Err(err) = self.source.release(&self.judge_id, ids).await {
+            error!("Unable to release leases on {:?}: {}", ids, err);
+        }
+    }
+}
+
+/// Requeues the jobs recovered from the state store, reattaching to containers that
+/// survived the restart. Jobs with a verdict in the outbox just wait for it to be delivered.
+pub fn recover(judge: &Judge, records: Vec<JobRecord>, queue: Sender<Job>) {
+    // Claim the ids before the poller starts so they aren't queued twice
+    {
+        let mut active = judge.active.lock().unwrap();
+        for record in &records {
+            active.insert(record.submission.id);
+        }
+    }
+
+    let judge = judge.clone();
+    tokio::spawn(async move {
+        for record in records {
+            let id = record.submission.id;
+            let container = format!("reverie_{}", id);
+
+            if judge.outbox.contains(id) {
+                info!("Result for submission '{}' is waiting to be delivered", id);
+                let _ = rm_container(container, judge.config().docker.url.clone()).await;
+                continue;
+            }
+            if record.status == JobStatus::Finished {
+                // Its verdict was acknowledged just before the judge stopped
+                judge.release(id);
+                continue;
+            }
+
+            let mut job = Job::new(record.submission);
+            job.status = record.status;
+            job.container = record.container;
+            job.started = record.started;
+
+            if job.status != JobStatus::Pending {
+                match container_state(container.clone(), judge.config().docker.url.clone()).await {
+                    Ok(_) => info!("Reattaching to container {}", container),
+                    // The job keeps polling the container once docker answers again
+                    Err(err) if !is_missing(&err) => warn!(
+                        "Unable to inspect container {}, reattaching anyway: {}",
+                        container, err
+                    ),
+                    Err(_) => {
+                        warn!(
+                            "Container for submission '{}' is gone, judging it again",
+                            id
+                        );
+                        let _ = tokio::fs::remove_dir_all(format!("./jobs/{}", id)).await;
+                        job.status = JobStatus::Pending;
+                        job.container = None;
+                        job.started = None;
+                        judge.save(&job);
+                    }
+                }
+            }
+
+            if queue.send(job).await.is_err() {
+                error!("Work queue has been closed, stopping recovery");
+                return;
+            }
+        }
+    });
+}
+
+/// Keeps the leases on every active submission alive while this judge works on them.
+pub async fn heartbeat(judge: Judge, every: Duration) {
+    let mut interval = tokio::time::interval(every);
+    loop {
+        interval.tick().await;
+        let ids: Vec<i32> = judge.active.lock().unwrap().iter().copied().collect();
+        if ids.is_empty() {
+            continue;
+        }
+        match judge.source.renew(&judge.judge_id, &ids).await {
+            Ok(held) => {
+                for id in ids.iter().filter(|id| !held.contains(id)) {
+                    warn!(
+                        "Lost the lease on submission '{}', its result may be rejected",
+                        id
+                    );
+                }
+            }
+            Err(err) => error!("Unable to renew leases: {}", err),
+        }
+    }
+}
+
+/// Takes jobs off the work queue and spawns a task for each one once enough cores
+/// and memory and then a slot are free. Once shutdown starts it stops dispatching and
+/// returns when every running job has finished, killing them after `deadline`.
+pub async fn dispatch(judge: Judge, mut queue: Receiver<Job>, deadline: Duration) {
+    let max_jobs = judge.config().max_jobs.unwrap_or(1);
+    let slots = Arc::new(Semaphore::new(max_jobs));
+    let capacity = Arc::new(AtomicUsize::new(max_jobs));
+    let resizer = tokio::spawn(resize_slots(judge.clone(), slots.clone(), capacity.clone()));
+    let mut mode = judge.mode.subscribe();
+    // Submissions taken but never started, handed back to ravel on shutdown
+    let mut unstarted = Vec::new();
+    loop {
+        let job = tokio::select! {
+            job = queue.recv() => match job {
+                Some(job) => job,
+                None => break,
+            },
+            _ = shutting_down(&mut mode) => break,
+        };
+        // Resources come first so a waiting job doesn't sit on a slot
+        let config = judge.config();
+        let memory = job
+            .submission
+            .memory_limit
+            .unwrap_or(config.resources.job_memory);
+        let reservation = tokio::select! {
+            reservation = judge.resources.reserve(config.resources.job_cores, memory) => reservation,
+            _ = shutting_down(&mut mode) => {
+                unstarted.push(job.submission.id);
+                break;
+            }
+        };
+        let reservation = match reservation {
+            Ok(reservation) => reservation,
+            Err(err) => {
+                // Another judge may be big enough for it
+                error!(
+                    "Submission '{}' can never be scheduled here, handing it back: {}",
+                    job.submission.id, err
+                );
+                judge.give_up(&[job.submission.id]).await;
+                continue;
+            }
+        };
+        let permit = tokio::select! {
+            permit = slots.clone().acquire_owned() => permit.expect("Job semaphore closed"),
+            _ = shutting_down(&mut mode) => {
+                unstarted.push(job.submission.id);
+                break;
+            }
+        };
+        let span = job.span.clone();
+        tokio::spawn(run_job(judge.clone(), job, permit, reservation).instrument(span));
+    }
+
+    queue.close();
+    while let Ok(job) = queue.try_recv() {
+        unstarted.push(job.submission.id);
+    }
+    if !unstarted.is_empty() {
+        info!(
+            "Releasing {} submissions that were never started",
+            unstarted.len()
+        );
+        judge.give_up(&unstarted).await;
+    }
+
+    info!("Waiting for running jobs to finish");
+    // Stopped first so the slots don't change while they are counted
+    resizer.abort();
+    let _ = resizer.await;
+    let all = capacity.load(Ordering::SeqCst) as u32;
+    if tokio::time::timeout(deadline, slots.acquire_many(all))
+        .await
+        .is_err()
+    {
+        warn!(
+            "Jobs still running after {}s, killing them",
+            deadline.as_secs()
+        );
+        judge.mode.send_replace(Mode::Aborting);
+        let _ = slots.acquire_many(all).await;
+    }
+}
+
+// Grows or shrinks the job slots when max_jobs is reloaded. Running jobs keep their
+// slots, shrinking takes effect as they finish.
+async fn resize_slots(judge: Judge, slots: Arc<Semaphore>, capacity: Arc<AtomicUsize>) {
+    let mut config = judge.config.subscribe();
+    while config.changed().await.is_ok() {
+        let max_jobs = config.borrow_and_update().max_jobs.unwrap_or(1);
+        let current = capacity.load(Ordering::SeqCst);
+        if max_jobs > current {
+            slots.add_permits(max_jobs - current);
+        } else if max_jobs < current {
+            match slots.acquire_many((current - max_jobs) as u32).await {
+                Ok(permits) => permits.forget(),
+                Err(_) => return,
+            }
+        }
+        capacity.store(max_jobs, Ordering::SeqCst);
+    }
+}
+
+async fn shutting_down(mode: &mut watch::Receiver<Mode>) {
+    let _ = mode.wait_for(|mode| *mode >= Mode::ShuttingDown).await;
+}
+
+/// Drives a single job through `JobStatus` and puts its result in the outbox.
+async fn run_job(
+    judge: Judge,
+    mut job: Job,
+    _permit: OwnedSemaphorePermit,
+    reservation: Reservation,
+) {
+    let id = job.submission.id;
+    let container = format!("reverie_{}", id);
+    // The problem stays cached until the job is done with it
+    let _pin = cache::pin(job.submission.problem);
+    // Reloading the configuration only affects jobs started after it
+    let config = judge.config();
+    let mut progress = Progress::Queued;
+    loop {
+        match job.status {
+            JobStatus::Pending => {
+                info!("Running submission '{}'", id);
+                match run_submission(
+                    &config.paths.work_dir,
+                    job.submission.clone(),
+                    judge.source.as_ref(),
+                    &reservation,
+                    &judge.judge_id,
+                    &config,
+                )
+                .await
+                {
+                    Ok(container_id) => {
+                        job.status = JobStatus::Running;
+                        job.container = Some(container_id);
+                        job.started = Some(Utc::now());
+                        judge.save(&job);
+                        info!("Judging submission '{}', has started", id)
+                    }
+                    Err(err) => {
+                        error!(
+                            "Encountered an error running submission '{}': '{}'",
+                            id, err
+                        );
+                        judge.give_up(&[id]).await;
+                        return;
+                    }
+                }
+            }
+            JobStatus::Running => {
+                tokio::time::sleep(Duration::from_millis(config.docker.poll_interval)).await;
+                if *judge.mode.borrow() == Mode::Aborting {
+                    warn!("Shutdown deadline passed, killing submission '{}'", id);
+                    let _ = kill_container(container.clone(), config.docker.url.clone()).await;
+                    let _ = rm_container(container.clone(), config.docker.url.clone()).await;
+                    let _ = tokio::fs::remove_dir_all(format!("./jobs/{}", id)).await;
+                    judge.give_up(&[id]).await;
+                    return;
+                }
+                if let Some(current) = read_progress(&job_dir(&config.paths.work_dir, id)).await {
+                    if current != progress {
+                        progress = current;
+                        judge.report(id, progress);
+                    }
+                }
+                match container_state(container.clone(), config.docker.url.clone()).await {
+                    // The container is gone, e.g. removed by the janitor
+                    Err(err) if is_missing(&err) => job.status = JobStatus::Finished,
+                    Err(err) => warn!("Unable to inspect container {}: {}", container, err),
+                    Ok(state) => {
+                        if !state.running {
+                            job.status = JobStatus::Finished;
+                        } else if let Some(started) = job.started {
+                            if (Utc::now() - started).num_seconds()
+                                >= config.docker.kill_timeout as i64
+                            {
+                                if let Err(err) =
+                                    kill_container(container.clone(), config.docker.url.clone())
+                                        .await
+                                {
+                                    error!("Unable to kill job {} with error: {}", id, err)
+                                }
+
+                                if let Err(err) = tokio::fs::write(
+                                    format!("./jobs/{}/status.txt", id),
+                                    "Timelimit Exception",
+                                )
+                                .await
+                                {
+                                    error!(
+                                    "Unable to write timeout for job '{}' with the following error: '{}'",
+                                    id, err
+                                );
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            JobStatus::Finished => {
+                info!("Submission '{}' has finished running", id);
+
+                let state =
+                    match container_state(container.clone(), config.docker.url.clone()).await {
+                        Ok(state) => state,
+                        // Retried until the shutdown deadline, then the job is handed back
+                        Err(err) if !is_missing(&err) && *judge.mode.borrow() != Mode::Aborting => {
+                            warn!("Unable to inspect container {}: {}", container, err);
+                            tokio::time::sleep(Duration::from_millis(config.docker.poll_interval))
+                                .await;
+                            continue;
+                        }
+                        Err(_) => {
+                            let _ = tokio::fs::remove_dir_all(format!("./jobs/{}", id)).await;
+
+                            if rm_container(container.clone(), config.docker.url.clone())
+                                .await
+                                .is_err()
+                            {
+                                error!("Unable to remove container {}", container)
+                            }
+                            judge.give_up(&[id]).await;
+                            return;
+                        }
+                    };
+                let result = JobResult::from_i32(state.exit_code);
+                let run = read_run(&job_dir(&config.paths.work_dir, id)).await;
+                // Without the image's timing, how long the container ran, compiling included
+                let runtime = run
+                    .time
+                    .or_else(|| match (state.started_at, state.finished_at) {
+                        (Some(started), Some(finished)) => {
+                            u64::try_from((finished - started).num_milliseconds()).ok()
+                        }
+                        _ => None,
+                    });
+
+                info!("Removing container {}", container);
+                // Containers that couldn't be removed are picked up by the janitor
+                if rm_container(container.clone(), config.docker.url.clone())
+                    .await
+                    .is_err()
+                {
+                    error!("Unable to remove container {}", container)
+                }
+
+                let mut solved = true;
+                let mut err = None;
+                if result != Some(Correct) {
+                    solved = false;
+                    err = result;
+                }
+
+                info!(
+                    "Submission '{}' has finished with the result solved: '{}', err: '{:?}'",
+                    id, solved, err
+                );
+
+                let result = FinishedSubmissions {
+                    id,
+                    solved,
+                    error: err,
+                    judging: job.submission.judging,
+                    runtime,
+                    output: run.stdout,
+                    errors: run.stderr,
+                    compiler: run.compiler,
+                };
+                if let Err(err) = judge.outbox.push(result).await {
+                    error!(
+                        "Unable to store result for '{}', judging it again: {}",
+                        id, err
+                    );
+                    judge.give_up(&[id]).await;
+                    return;
+                }
+                // Skipped if the reporter already delivered the verdict and released the job
+                job.status = JobStatus::Finished;
+                judge.save(&job);
+                judge.report(id, Progress::Finished);
+                return;
+            }
+        }
+    }
+}
+
+/// Sends progress updates to ravel in the order they happened.
+pub async fn report_progress(judge: Judge, mut updates: Receiver<(i32, Progress)>) {
+    while let Some((id, progress)) = updates.recv().await {
+        if let Err(err) = judge.source.progress(&judge.judge_id, id, progress).await {
+            warn!("{:#}", err);
+        }
+    }
+}
+
+/// Delivers the outbox to ravel whenever it has verdicts in it.
+pub async fn report_results(judge: Judge) {
+    loop {
+        judge.outbox.wait().await;
+        match deliver(&judge).await {
+            // Verdicts ravel didn't acknowledge are resent on the next round
+            Ok(true) => {}
+            Ok(false) => tokio::time::sleep(Duration::from_secs(1)).await,
+            Err(err) => {
+                error!("Unable to update submissions on ravel, with error: {}", err);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+/// Delivers the outbox until it is empty.
+pub async fn flush(judge: &Judge) {
+    loop {
+        match deliver(judge).await {
+            Ok(true) => return,
+            Ok(false) => {}
+            Err(err) => error!("Unable to update submissions on ravel, with error: {}", err),
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+// Posts every verdict in the outbox and forgets the ones ravel acknowledged.
+// Returns whether the outbox is now empty.
+async fn deliver(judge: &Judge) -> Result<bool> {
+    let submissions = judge.outbox.pending();
+    if submissions.is_empty() {
+        return Ok(true);
+    }
+    let sent: HashSet<i32> = submissions.iter().map(|result| result.id).collect();
+    let acknowledged: Vec<i32> = judge
+        .source
+        .report(submissions)
+        .await?
+        .into_iter()
+        .filter(|id| sent.contains(id))
+        .collect();
+
+    judge.outbox.acknowledge(&acknowledged).await?;
+    for id in &acknowledged {
+        let _ = tokio::fs::remove_dir_all(format!("./jobs/{}", id)).await;
+        judge.release(*id);
+    }
+    Ok(acknowledged.len() == sent.len())
+}
@@ -0,0 +1,35 @@
+use crate::scheduler::{Judge, Mode};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info};
+
+/// Handles signals until SIGTERM or SIGINT is received. SIGUSR1 toggles drain mode,
+/// in which the judge finishes the jobs it has but doesn't fetch new ones.
+pub async fn wait_for_shutdown(judge: &Judge) {
+    let mut terminate = signal(SignalKind::terminate()).expect("Unable to listen for SIGTERM");
+    let mut interrupt = signal(SignalKind::interrupt()).expect("Unable to listen for SIGINT");
+    let mut drain = signal(SignalKind::user_defined1()).expect("Unable to listen for SIGUSR1");
+
+    loop {
+        tokio::select! {
+            _ = terminate.recv() => break,
+            _ = interrupt.recv() => break,
+            _ = drain.recv() => {
+                let mode = *judge.mode.borrow();
+                match mode {
+                    Mode::Serving => {
+                        info!("Draining, no new submissions will be fetched");
+                        judge.mode.send_replace(Mode::Draining);
+                    }
+                    Mode::Draining => {
+                        info!("Leaving drain mode, fetching submissions again");
+                        judge.mode.send_replace(Mode::Serving);
+                    }
+                    _ => error!("Already shutting down, ignoring SIGUSR1"),
+                }
+            }
+        }
+    }
+
+    info!("Shutting down");
+    judge.mode.send_replace(Mode::ShuttingDown);
+}
@@ -0,0 +1,228 @@
+use crate::cache::{Part, ProblemSink};
+use crate::config::{Config, SourceKind};
+use crate::domjudge::DomJudgeClient;
+use crate::ravel::{ActiveProblem, Credentials, RavelClient, RetryPolicy, Update, Verdict};
+use crate::runner::JobResult;
+use crate::Languages;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Submission {
+    // Submission id
+    pub id: i32,
+    // Time code is in
+    pub language: Languages,
+    // Code
+    pub content: String,
+    // Problem id
+    pub problem: i32,
+    // Input file checksum
+    pub input_sum: String,
+    // Output file checksum
+    pub output_sum: String,
+    // Timelimit
+    pub timeout: i32,
+    // The time limit in milliseconds when it isn't a whole number of seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    // Memory limit in MB, falls back to the judge's job_memory
+    #[serde(default)]
+    pub memory_limit: Option<u64>,
+    // The platform's judging this run is part of, DOMjudge needs it to report compile errors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub judging: Option<i32>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Problem {
+    pub problem_input: String,
+    pub problem_output: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct FinishedSubmissions {
+    pub id: i32,
+    pub solved: bool,
+    pub error: Option<JobResult>,
+    // Carried over from the submission
+    #[serde(default)]
+    pub judging: Option<i32>,
+    // Milliseconds the solution ran
+    #[serde(default)]
+    pub runtime: Option<u64>,
+    // What the solution wrote to stdout and stderr and what the compiler printed,
+    // when the judge image kept them
+    #[serde(default)]
+    pub output: Option<String>,
+    #[serde(default)]
+    pub errors: Option<String>,
+    #[serde(default)]
+    pub compiler: Option<String>,
+}
+
+/// What the judge is doing with a submission, shown to contestants while they wait.
+#[derive(Serialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Progress {
+    Queued,
+    Compiling,
+    Running { test: u32, tests: u32 },
+    Finished,
+}
+
+/// A platform the judge takes submissions from and reports verdicts to.
+#[async_trait]
+pub trait SubmissionSource: Send + Sync {
+    /// Takes up to `count` pending submissions for this judge.
+    async fn pending(&self, judge: &str, count: usize) -> Result<Vec<Submission>>;
+
+    /// Like `pending`, but may wait up to `wait` for submissions to arrive.
+    async fn pending_wait(
+        &self,
+        judge: &str,
+        count: usize,
+        _wait: Duration,
+    ) -> Result<Vec<Submission>> {
+        self.pending(judge, count).await
+    }
+
+    /// Input and expected output for a problem.
+    async fn problem(&self, problem_id: i32) -> Result<Problem>;
+
+    /// Writes a problem's input and expected output into `sink`. Sources that can't
+    /// stream fetch the whole problem first.
+    async fn download(&self, problem_id: i32, sink: &mut ProblemSink) -> Result<()> {
+        let problem = self.problem(problem_id).await?;
+        sink.write(Part::Input, problem.problem_input.as_bytes())
+            .await?;
+        sink.write(Part::Output, problem.problem_output.as_bytes())
+            .await
+    }
+
+    /// Problems worth caching before submissions for them arrive. Sources that
+    /// can't list their problems have nothing to prefetch.
+    async fn active_problems(&self) -> Result<Vec<ActiveProblem>> {
+        Ok(Vec::new())
+    }
+
+    /// Reports verdicts, returning the ids of the submissions the platform recorded.
+    async fn report(&self, results: Vec<FinishedSubmissions>) -> Result<Vec<i32>>;
+
+    async fn progress(&self, judge: &str, id: i32, progress: Progress) -> Result<()>;
+
+    /// Keeps this judge's claim on `ids`, returning the ids it still holds. Platforms
+    /// without leases hold on to everything.
+    async fn renew(&self, _judge: &str, ids: &[i32]) -> Result<Vec<i32>> {
+        Ok(ids.to_vec())
+    }
+
+    /// Hands `ids` back so another judge can take them.
+    async fn release(&self, _judge: &str, _ids: &[i32]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Makes a request that changes nothing, failing unless the platform is
+    /// reachable and accepts the judge's credentials.
+    async fn check(&self, _judge: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The client for the source the configuration names. Ravel is the default,
+/// debussy can also act as a DOMjudge judgehost.
+pub fn connect(config: &Config) -> Result<Arc<dyn SubmissionSource>> {
+    let timeout = Duration::from_secs(config.ravel.timeout);
+    Ok(match config.source {
+        SourceKind::Ravel => {
+            let ravel = &config.ravel;
+            // A static api key takes precedence over logging in with a username and password
+            let credentials = match &ravel.api_key {
+                Some(key) => Credentials::ApiKey(key.clone()),
+                None => Credentials::Password {
+                    username: ravel.username.clone().unwrap_or_default(),
+                    password: ravel.password.clone().unwrap_or_default(),
+                },
+            };
+            Arc::new(
+                RavelClient::new(
+                    ravel.url.clone().unwrap_or_default(),
+                    credentials,
+                    timeout,
+                    RetryPolicy {
+                        attempts: ravel.retries,
+                        base_delay: Duration::from_millis(250),
+                        max_delay: Duration::from_secs(10),
+                    },
+                )
+                .context("Unable to create ravel client")?,
+            )
+        }
+        SourceKind::Domjudge => {
+            let domjudge = &config.domjudge;
+            Arc::new(
+                DomJudgeClient::new(
+                    domjudge.url.clone().unwrap_or_default(),
+                    domjudge.username.clone().unwrap_or_default(),
+                    domjudge.password.clone().unwrap_or_default(),
+                    timeout,
+                )
+                .context("Unable to create DOMjudge client")?,
+            )
+        }
+    })
+}
+
+#[async_trait]
+impl SubmissionSource for RavelClient {
+    async fn pending(&self, judge: &str, count: usize) -> Result<Vec<Submission>> {
+        RavelClient::pending(self, judge, count).await
+    }
+
+    async fn pending_wait(
+        &self,
+        judge: &str,
+        count: usize,
+        wait: Duration,
+    ) -> Result<Vec<Submission>> {
+        RavelClient::pending_wait(self, judge, count, wait).await
+    }
+
+    async fn problem(&self, problem_id: i32) -> Result<Problem> {
+        RavelClient::problem(self, problem_id).await
+    }
+
+    async fn download(&self, problem_id: i32, sink: &mut ProblemSink) -> Result<()> {
+        RavelClient::download(self, problem_id, sink).await
+    }
+
+    async fn active_problems(&self) -> Result<Vec<ActiveProblem>> {
+        RavelClient::active_problems(self).await
+    }
+
+    async fn report(&self, results: Vec<FinishedSubmissions>) -> Result<Vec<i32>> {
+        self.update(&Update {
+            submissions: results.iter().map(Verdict::from).collect(),
+        })
+        .await
+    }
+
+    async fn progress(&self, judge: &str, id: i32, progress: Progress) -> Result<()> {
+        RavelClient::progress(self, judge, id, progress).await
+    }
+
+    async fn renew(&self, judge: &str, ids: &[i32]) -> Result<Vec<i32>> {
+        RavelClient::renew(self, judge, ids).await
+    }
+
+    async fn release(&self, judge: &str, ids: &[i32]) -> Result<()> {
+        RavelClient::release(self, judge, ids).await
+    }
+
+    async fn check(&self, judge: &str) -> Result<()> {
+        RavelClient::check(self, judge).await
+    }
+}
@@ -0,0 +1,169 @@
+use crate::runner::JobStatus;
+use crate::source::Submission;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use tokio::sync::oneshot;
+use tracing::{error, warn};
+
+// Everything needed to pick a job back up after the judge restarts
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct JobRecord {
+    pub submission: Submission,
+    pub status: JobStatus,
+    pub container: Option<String>,
+    pub started: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+enum Entry {
+    Update(Box<JobRecord>),
+    Remove(i32),
+}
+
+enum Command {
+    Entry(Entry),
+    // Answered once everything sent before it is on disk
+    Flush(oneshot::Sender<()>),
+}
+
+/// Append-only journal of job state, one JSON entry per line. Entries are written
+/// and synced by a dedicated thread so job tasks never wait on the disk.
+pub struct StateStore {
+    writer: Sender<Command>,
+}
+
+impl StateStore {
+    /// Opens the journal in `dir`, returning the jobs that were still live when the
+    /// judge stopped. The journal is compacted down to those jobs on every open.
+    pub fn open(dir: &Path) -> Result<(Self, Vec<JobRecord>)> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Unable to create state dir {}", dir.display()))?;
+        let path = dir.join("journal.jsonl");
+
+        let records = if path.exists() {
+            replay(&path)?
+        } else {
+            BTreeMap::new()
+        };
+
+        // Rewrite the journal with only the live jobs, then swap it in
+        let compacted = dir.join("journal.jsonl.tmp");
+        let mut file = File::create(&compacted)
+            .with_context(|| format!("Unable to create {}", compacted.display()))?;
+        for record in records.values() {
+            write_entry(&mut file, &Entry::Update(Box::new(record.clone())))?;
+        }
+        file.sync_all()?;
+        fs::rename(&compacted, &path)
+            .with_context(|| format!("Unable to replace {}", path.display()))?;
+
+        let journal = OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Unable to open {}", path.display()))?;
+
+        let (writer, writes) = mpsc::channel();
+        thread::Builder::new()
+            .name(String::from("state-journal"))
+            .spawn(move || write_journal(journal, writes))
+            .context("Unable to start the state journal writer")?;
+
+        Ok((StateStore { writer }, records.into_values().collect()))
+    }
+
+    pub fn update(&self, record: JobRecord) -> Result<()> {
+        self.send(Command::Entry(Entry::Update(Box::new(record))))
+    }
+
+    pub fn remove(&self, id: i32) -> Result<()> {
+        self.send(Command::Entry(Entry::Remove(id)))
+    }
+
+    /// Waits until every update and removal made so far is on disk.
+    pub async fn flush(&self) -> Result<()> {
+        let (done, flushed) = oneshot::channel();
+        self.send(Command::Flush(done))?;
+        flushed
+            .await
+            .context("State journal writer stopped before flushing")
+    }
+
+    fn send(&self, command: Command) -> Result<()> {
+        self.writer
+            .send(command)
+            .context("State journal writer has stopped")
+    }
+}
+
+// Writes whatever has queued up since the last sync, then syncs once for all of it
+fn write_journal(mut journal: File, writes: Receiver<Command>) {
+    while let Ok(first) = writes.recv() {
+        let mut flushes = Vec::new();
+        let mut lines = Vec::new();
+        for command in std::iter::once(first).chain(writes.try_iter()) {
+            match command {
+                Command::Entry(entry) => match serde_json::to_vec(&entry) {
+                    Ok(mut line) => {
+                        line.push(b'\n');
+                        lines.extend(line);
+                    }
+                    Err(err) => error!("Unable to serialize state journal entry: {}", err),
+                },
+                Command::Flush(done) => flushes.push(done),
+            }
+        }
+        if let Err(err) = append(&mut journal, &lines) {
+            error!("{:#}", err);
+        }
+        for done in flushes {
+            let _ = done.send(());
+        }
+    }
+}
+
+fn replay(path: &PathBuf) -> Result<BTreeMap<i32, JobRecord>> {
+    let file = File::open(path).with_context(|| format!("Unable to open {}", path.display()))?;
+    let mut records = BTreeMap::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        match serde_json::from_str::<Entry>(&line) {
+            Ok(Entry::Update(record)) => {
+                records.insert(record.submission.id, *record);
+            }
+            Ok(Entry::Remove(id)) => {
+                records.remove(&id);
+            }
+            // A crash mid-write can leave a torn last line
+            Err(err) => warn!(
+                "Skipping unreadable journal entry on line {}: {}",
+                number + 1,
+                err
+            ),
+        }
+    }
+    Ok(records)
+}
+
+fn write_entry(file: &mut File, entry: &Entry) -> Result<()> {
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    file.write_all(&line)
+        .context("Unable to append to state journal")
+}
+
+fn append(file: &mut File, lines: &[u8]) -> Result<()> {
+    if lines.is_empty() {
+        return Ok(());
+    }
+    file.write_all(lines)
+        .context("Unable to append to state journal")?;
+    file.sync_data().context("Unable to sync state journal")?;
+    Ok(())
+}
@@ -0,0 +1,336 @@
+use crate::runner::JobResult::{
+    self, CompilerError, Correct, RuntimeError, TimelimitException, Wrong,
+};
+use crate::Languages;
+
+// The suite's problem: add the two numbers on the input
+pub const INPUT: &str = "2 3\n";
+pub const ANSWER: &str = "5\n";
+// Seconds and MB
+pub const TIMEOUT: i32 = 2;
+pub const MEMORY: u64 = 256;
+
+/// A program with a known verdict on the suite's problem.
+pub struct Case {
+    pub name: &'static str,
+    pub language: Languages,
+    // Verdicts that show the sandbox did its job
+    pub expected: &'static [JobResult],
+    // Whether the container has to be killed for going over its memory limit
+    pub oom_killed: bool,
+    pub source: &'static str,
+}
+
+/// The suite `doctor` runs. The network cases print the right answer only when
+/// they can't connect out, and fork bombs exit with an error once the pids limit
+/// stops them. Python is interpreted, so it has no compile error case.
+pub const CASES: [Case; 23] = [
+    Case {
+        name: "accepted",
+        language: Languages::Python,
+        expected: &[Correct],
+        oom_killed: false,
+        source: "a, b = map(int, input().split())\nprint(a + b)\n",
+    },
+    Case {
+        name: "wrong_answer",
+        language: Languages::Python,
+        expected: &[Wrong],
+        oom_killed: false,
+        source: "a, b = map(int, input().split())\nprint(a + b + 1)\n",
+    },
+    Case {
+        name: "time_limit",
+        language: Languages::Python,
+        expected: &[TimelimitException],
+        oom_killed: false,
+        source: "while True:\n    pass\n",
+    },
+    Case {
+        name: "runtime_error",
+        language: Languages::Python,
+        expected: &[RuntimeError],
+        oom_killed: false,
+        source: "print(1 // 0)\n",
+    },
+    Case {
+        name: "memory_hog",
+        language: Languages::Python,
+        expected: &[RuntimeError],
+        oom_killed: true,
+        source: "hog = []\nwhile True:\n    hog.append(bytearray(b\"x\") * (64 << 20))\n",
+    },
+    Case {
+        name: "fork_bomb",
+        language: Languages::Python,
+        expected: &[RuntimeError],
+        oom_killed: false,
+        source: "import os\nwhile True:\n    os.fork()\n",
+    },
+    Case {
+        name: "network",
+        language: Languages::Python,
+        expected: &[Correct],
+        oom_killed: false,
+        source: r#"import socket
+a, b = map(int, input().split())
+try:
+    socket.create_connection(("1.1.1.1", 53), timeout=1)
+    print("connected")
+except OSError:
+    print(a + b)
+"#,
+    },
+    Case {
+        name: "accepted",
+        language: Languages::Java,
+        expected: &[Correct],
+        oom_killed: false,
+        source: r#"import java.util.Scanner;
+
+class Main {
+    public static void main(String[] args) {
+        Scanner in = new Scanner(System.in);
+        System.out.println(in.nextLong() + in.nextLong());
+    }
+}
+"#,
+    },
+    Case {
+        name: "wrong_answer",
+        language: Languages::Java,
+        expected: &[Wrong],
+        oom_killed: false,
+        source: r#"import java.util.Scanner;
+
+class Main {
+    public static void main(String[] args) {
+        Scanner in = new Scanner(System.in);
+        System.out.println(in.nextLong() + in.nextLong() + 1);
+    }
+}
+"#,
+    },
+    Case {
+        name: "time_limit",
+        language: Languages::Java,
+        expected: &[TimelimitException],
+        oom_killed: false,
+        source: r#"class Main {
+    public static void main(String[] args) {
+        while (true) {}
+    }
+}
+"#,
+    },
+    Case {
+        name: "runtime_error",
+        language: Languages::Java,
+        expected: &[RuntimeError],
+        oom_killed: false,
+        source: r#"class Main {
+    public static void main(String[] args) {
+        throw new RuntimeException("doctor");
+    }
+}
+"#,
+    },
+    Case {
+        name: "compile_error",
+        language: Languages::Java,
+        expected: &[CompilerError],
+        oom_killed: false,
+        source: r#"class Main {
+    public static void main(String[] args) {
+        int answer = "doctor";
+    }
+}
+"#,
+    },
+    Case {
+        name: "memory_hog",
+        language: Languages::Java,
+        expected: &[RuntimeError],
+        oom_killed: true,
+        // Native memory, the JVM would stop a heap hog itself before the container limit
+        source: r#"import java.lang.reflect.Field;
+import sun.misc.Unsafe;
+
+class Main {
+    public static void main(String[] args) throws Exception {
+        Field field = Unsafe.class.getDeclaredField("theUnsafe");
+        field.setAccessible(true);
+        Unsafe unsafe = (Unsafe) field.get(null);
+        while (true) {
+            long block = unsafe.allocateMemory(64 << 20);
+            unsafe.setMemory(block, 64 << 20, (byte) 1);
+        }
+    }
+}
+"#,
+    },
+    Case {
+        name: "fork_bomb",
+        language: Languages::Java,
+        expected: &[RuntimeError],
+        oom_killed: false,
+        source: r#"class Main {
+    public static void main(String[] args) {
+        while (true) {
+            new Thread(() -> {
+                try {
+                    Thread.sleep(Long.MAX_VALUE);
+                } catch (InterruptedException e) {
+                }
+            }).start();
+        }
+    }
+}
+"#,
+    },
+    Case {
+        name: "network",
+        language: Languages::Java,
+        expected: &[Correct],
+        oom_killed: false,
+        source: r#"import java.net.InetSocketAddress;
+import java.net.Socket;
+import java.util.Scanner;
+
+class Main {
+    public static void main(String[] args) {
+        Scanner in = new Scanner(System.in);
+        long sum = in.nextLong() + in.nextLong();
+        try (Socket socket = new Socket()) {
+            socket.connect(new InetSocketAddress("1.1.1.1", 53), 1000);
+            System.out.println("connected");
+        } catch (Exception e) {
+            System.out.println(sum);
+        }
+    }
+}
+"#,
+    },
+    Case {
+        name: "accepted",
+        language: Languages::Cpp,
+        expected: &[Correct],
+        oom_killed: false,
+        source: r#"#include <iostream>
+
+int main() {
+    long long a, b;
+    std::cin >> a >> b;
+    std::cout << a + b << std::endl;
+}
+"#,
+    },
+    Case {
+        name: "wrong_answer",
+        language: Languages::Cpp,
+        expected: &[Wrong],
+        oom_killed: false,
+        source: r#"#include <iostream>
+
+int main() {
+    long long a, b;
+    std::cin >> a >> b;
+    std::cout << a + b + 1 << std::endl;
+}
+"#,
+    },
+    Case {
+        name: "time_limit",
+        language: Languages::Cpp,
+        expected: &[TimelimitException],
+        oom_killed: false,
+        source: r#"int main() {
+    volatile unsigned long long spins = 0;
+    while (true) {
+        spins++;
+    }
+}
+"#,
+    },
+    Case {
+        name: "runtime_error",
+        language: Languages::Cpp,
+        expected: &[RuntimeError],
+        oom_killed: false,
+        source: r#"#include <stdexcept>
+
+int main() {
+    throw std::runtime_error("doctor");
+}
+"#,
+    },
+    Case {
+        name: "compile_error",
+        language: Languages::Cpp,
+        expected: &[CompilerError],
+        oom_killed: false,
+        source: "int main() {\n    return \"doctor\";\n}\n",
+    },
+    Case {
+        name: "memory_hog",
+        language: Languages::Cpp,
+        expected: &[RuntimeError],
+        oom_killed: true,
+        source: r#"#include <cstdlib>
+#include <cstring>
+
+int main() {
+    while (true) {
+        char *block = static_cast<char *>(malloc(64 << 20));
+        if (block == nullptr) {
+            return 1;
+        }
+        memset(block, 1, 64 << 20);
+    }
+}
+"#,
+    },
+    Case {
+        name: "fork_bomb",
+        language: Languages::Cpp,
+        expected: &[RuntimeError],
+        oom_killed: false,
+        source: r#"#include <cstdio>
+#include <unistd.h>
+
+int main() {
+    while (true) {
+        if (fork() < 0) {
+            perror("fork");
+            return 1;
+        }
+    }
+}
+"#,
+    },
+    Case {
+        name: "network",
+        language: Languages::Cpp,
+        expected: &[Correct],
+        oom_killed: false,
+        source: r#"#include <arpa/inet.h>
+#include <iostream>
+#include <sys/socket.h>
+
+int main() {
+    long long a, b;
+    std::cin >> a >> b;
+    int fd = socket(AF_INET, SOCK_STREAM, 0);
+    sockaddr_in addr{};
+    addr.sin_family = AF_INET;
+    addr.sin_port = htons(53);
+    inet_pton(AF_INET, "1.1.1.1", &addr.sin_addr);
+    if (fd >= 0 && connect(fd, reinterpret_cast<sockaddr *>(&addr), sizeof addr) == 0) {
+        std::cout << "connected" << std::endl;
+    } else {
+        std::cout << a + b << std::endl;
+    }
+}
+"#,
+    },
+];
@@ -0,0 +1,294 @@
+use crate::config::Config;
+use crate::local::{format_time, format_verdict, guess_language, LocalJudge, ScratchDir};
+use crate::problem::{LocalProblem, Solution, ValidatorKind};
+use crate::program::Program;
+use crate::runner::JobResult;
+use anyhow::{Context, Result};
+use clap::Args;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+// Exit code a Kattis style input validator uses to accept an input, testlib ones exit 0
+const VALIDATOR_ACCEPTS: i32 = 42;
+
+#[derive(Args, Debug)]
+pub struct ValidateArgs {
+    /// Problem directory, a Kattis/ICPC or Polygon package or the layout in the README
+    pub problem: PathBuf,
+    /// Memory in MB when the problem doesn't set a limit
+    #[arg(long, default_value_t = 1024)]
+    pub memory: u64,
+    /// Warn when an accepted solution takes more than this percent of the time limit
+    #[arg(long, default_value_t = 50)]
+    pub margin: u32,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Level {
+    Ok,
+    Mismatch,
+    Warning,
+    // Said once about the whole problem
+    Note,
+}
+
+/// Something `validate-problem` found, as printed on one line.
+#[derive(Serialize, Debug)]
+struct Finding {
+    level: Level,
+    detail: String,
+}
+
+#[derive(Serialize, Debug)]
+struct Report {
+    valid: bool,
+    discrepancies: usize,
+    findings: Vec<Finding>,
+    // Printed as JSON at the end rather than finding by finding
+    #[serde(skip)]
+    json: bool,
+}
+
+impl Report {
+    fn record(&mut self, level: Level, detail: String) {
+        if level == Level::Mismatch {
+            self.discrepancies += 1;
+        }
+        if !self.json {
+            match level {
+                Level::Ok => println!("ok       {}", detail),
+                Level::Mismatch => println!("MISMATCH {}", detail),
+                Level::Warning => println!("WARNING  {}", detail),
+                Level::Note => println!("{}", detail),
+            }
+        }
+        self.findings.push(Finding { level, detail });
+    }
+}
+
+/// Checks a problem package: every input passes the input validators, accepted
+/// solutions pass every test and the other solutions get the verdict their
+/// directory names. Returns whether everything matched.
+pub async fn validate(args: ValidateArgs, config: &Config, json: bool) -> Result<bool> {
+    let problem = LocalProblem::load(&args.problem)?;
+    let scratch = ScratchDir::create()?;
+    let mut report = Report {
+        valid: false,
+        discrepancies: 0,
+        findings: Vec::new(),
+        json,
+    };
+
+    for validator in &problem.validators {
+        let path = &validator.path;
+        let program = Program::build(path, &problem.includes, &scratch.bin()).await?;
+        for test in &problem.tests {
+            if !run_validator(&program, validator.kind, path, &test.input).await? {
+                report.record(
+                    Level::Mismatch,
+                    format!("{} rejects test {}", path.display(), test.name),
+                );
+            }
+        }
+    }
+
+    if problem.solutions.is_empty() {
+        report.record(
+            Level::Note,
+            String::from("No solutions in submissions/, only the tests were checked"),
+        );
+    }
+    let local = LocalJudge::new(&problem, args.memory, &scratch, config).await?;
+    for solution in &problem.solutions {
+        check_solution(&mut report, &problem, &local, solution, args.margin).await?;
+    }
+
+    report.valid = report.discrepancies == 0;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else if report.valid {
+        println!("Problem is valid");
+    } else {
+        println!("{} discrepancies found", report.discrepancies);
+    }
+    Ok(report.valid)
+}
+
+// Validators get the input on stdin
+async fn run_validator(
+    validator: &Program,
+    kind: ValidatorKind,
+    path: &Path,
+    input: &Path,
+) -> Result<bool> {
+    let input =
+        fs::File::open(input).with_context(|| format!("Unable to open {}", input.display()))?;
+    let status = validator
+        .command()
+        .stdin(Stdio::from(input))
+        .stdout(Stdio::null())
+        .status()
+        .await
+        .with_context(|| format!("Unable to run validator {}", path.display()))?;
+    Ok(match kind {
+        ValidatorKind::Kattis => status.code() == Some(VALIDATOR_ACCEPTS),
+        ValidatorKind::Testlib => status.success(),
+    })
+}
+
+// Runs a solution until it gets a verdict other than Correct, which has to be the
+// expected one. Accepted solutions run every test and are checked for margin.
+async fn check_solution(
+    report: &mut Report,
+    problem: &LocalProblem,
+    local: &LocalJudge,
+    solution: &Solution,
+    margin: u32,
+) -> Result<()> {
+    let name = solution.path.display();
+    let Some(language) = guess_language(&solution.path) else {
+        report.record(
+            Level::Mismatch,
+            format!("{} is not in a known language", name),
+        );
+        return Ok(());
+    };
+    let content = fs::read_to_string(&solution.path)
+        .with_context(|| format!("Unable to read {}", solution.path.display()))?;
+
+    let mut slowest = Duration::ZERO;
+    let mut verdict = JobResult::Correct;
+    let mut failed_test = None;
+    for (index, test) in problem.tests.iter().enumerate() {
+        let outcome = local.run(language, &content, index).await?;
+        slowest = slowest.max(outcome.time.unwrap_or_default());
+        if outcome.result != Some(JobResult::Correct) {
+            // Unknown exit codes count as runtime errors
+            verdict = outcome.result.unwrap_or(JobResult::RuntimeError);
+            failed_test = Some(test.name.as_str());
+            break;
+        }
+    }
+
+    let limit = local.time_limit();
+    if verdict != solution.expected {
+        report.record(
+            Level::Mismatch,
+            format!(
+                "{} expected {:?} but got {}{}",
+                name,
+                solution.expected,
+                format_verdict(Some(verdict)),
+                failed_test
+                    .map(|test| format!(" on test {}", test))
+                    .unwrap_or_default()
+            ),
+        );
+        return Ok(());
+    }
+
+    report.record(
+        Level::Ok,
+        format!(
+            "{} {:?}, slowest {}",
+            name,
+            verdict,
+            format_time(Some(slowest))
+        ),
+    );
+    // Without a time limit there is nothing to compare against
+    if verdict == JobResult::Correct && !limit.is_zero() && slowest * 100 > limit * margin {
+        report.record(
+            Level::Warning,
+            format!(
+                "{} uses {:.0}% of the {} time limit",
+                name,
+                slowest.as_secs_f64() * 100.0 / limit.as_secs_f64(),
+                format_time(Some(limit))
+            ),
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A Polygon package with one test and a testlib style validator that exits 0
+    // for inputs of two numbers
+    fn polygon_package(dir: &Path) {
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir.join("tests")).unwrap();
+        fs::create_dir_all(dir.join("files")).unwrap();
+        fs::write(dir.join("tests/01"), "2 3\n").unwrap();
+        fs::write(dir.join("tests/01.a"), "5\n").unwrap();
+        fs::write(
+            dir.join("files/validator.py"),
+            "import sys\nsys.exit(0 if len(sys.stdin.read().split()) == 2 else 3)\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("problem.xml"),
+            r#"<problem>
+    <judging>
+        <testset name="tests">
+            <time-limit>1500</time-limit>
+            <memory-limit>268435456</memory-limit>
+            <test-count>1</test-count>
+            <input-path-pattern>tests/%02d</input-path-pattern>
+            <answer-path-pattern>tests/%02d.a</answer-path-pattern>
+        </testset>
+    </judging>
+    <assets>
+        <validators>
+            <validator>
+                <source path="files/validator.py" type="python.3"/>
+            </validator>
+        </validators>
+    </assets>
+</problem>
+"#,
+        )
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn polygon_validator() {
+        let dir = std::env::temp_dir().join(format!("debussy-polygon-{}", std::process::id()));
+        polygon_package(&dir);
+        let problem = LocalProblem::load(&dir).unwrap();
+        assert_eq!(problem.limits.time_limit, 1500);
+        let [validator] = problem.validators.as_slice() else {
+            panic!("Expected one validator, got {:?}", problem.validators);
+        };
+        assert_eq!(validator.kind, ValidatorKind::Testlib);
+
+        let program = Program::build(&validator.path, &problem.includes, &dir)
+            .await
+            .unwrap();
+        let input = &problem.tests[0].input;
+        assert!(
+            run_validator(&program, validator.kind, &validator.path, input)
+                .await
+                .unwrap()
+        );
+        // The same exit code is a rejection from a Kattis validator
+        assert!(
+            !run_validator(&program, ValidatorKind::Kattis, &validator.path, input)
+                .await
+                .unwrap()
+        );
+        fs::write(input, "2 3 4\n").unwrap();
+        assert!(
+            !run_validator(&program, validator.kind, &validator.path, input)
+                .await
+                .unwrap()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}